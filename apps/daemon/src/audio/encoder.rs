@@ -24,11 +24,11 @@ impl AudioEncoder {
 
         let parser = gst::ElementFactory::make("aacparse")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing aacparse element"))?;
+            .map_err(|_| io::Error::other("missing aacparse element"))?;
 
         let capsfilter = gst::ElementFactory::make("capsfilter")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing capsfilter"))?;
+            .map_err(|_| io::Error::other("missing capsfilter"))?;
 
         let caps = gst::Caps::builder("audio/mpeg")
             .field("mpegversion", 4i32)
@@ -38,13 +38,11 @@ impl AudioEncoder {
         capsfilter.set_property("caps", &caps);
 
         pipeline
-            .add_many(&[&encoder, &parser, &capsfilter])
-            .map_err(|_| {
-                io::Error::new(io::ErrorKind::Other, "failed to add elements to pipeline")
-            })?;
+            .add_many([&encoder, &parser, &capsfilter])
+            .map_err(|_| io::Error::other("failed to add elements to pipeline"))?;
 
-        gst::Element::link_many(&[&input.element, &encoder, &parser, &capsfilter])
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to link elements"))?;
+        gst::Element::link_many([&input.element, &encoder, &parser, &capsfilter])
+            .map_err(|_| io::Error::other("failed to link elements"))?;
 
         Ok(GraphOutput {
             element: capsfilter,
@@ -63,5 +61,5 @@ fn make_audio_encoder() -> io::Result<gst::Element> {
         return Ok(enc);
     }
 
-    Err(io::Error::new(io::ErrorKind::Other, "missing AAC encoder"))
+    Err(io::Error::other("missing AAC encoder"))
 }
@@ -34,27 +34,27 @@ impl AudioMixer {
     ) -> io::Result<AudioSourceOutput> {
         let mixer = gst::ElementFactory::make("audiomixer")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing audiomixer"))?;
+            .map_err(|_| io::Error::other("missing audiomixer"))?;
 
-        mixer.set_property("ignore-inactive-pads", &true);
+        mixer.set_property("ignore-inactive-pads", true);
 
         pipeline
             .add(&mixer)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to add audiomixer"))?;
+            .map_err(|_| io::Error::other("failed to add audiomixer"))?;
 
         for input in inputs {
             let src_pad = input
                 .element
                 .static_pad("src")
-                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing src pad"))?;
+                .ok_or_else(|| io::Error::other("missing src pad"))?;
 
-            let sink_pad = mixer.request_pad_simple("sink_%u").ok_or_else(|| {
-                io::Error::new(io::ErrorKind::Other, "failed to request mixer pad")
-            })?;
+            let sink_pad = mixer
+                .request_pad_simple("sink_%u")
+                .ok_or_else(|| io::Error::other("failed to request mixer pad"))?;
 
             src_pad
                 .link(&sink_pad)
-                .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to link into mixer"))?;
+                .map_err(|_| io::Error::other("failed to link into mixer"))?;
         }
 
         Ok(AudioSourceOutput {
@@ -30,33 +30,33 @@ impl MicAudioSource {
     ) -> io::Result<AudioSourceOutput> {
         let src = gst::ElementFactory::make("wasapisrc")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing wasapisrc"))?;
+            .map_err(|_| io::Error::other("missing wasapisrc"))?;
 
-        src.set_property("do-timestamp", &true);
+        src.set_property("do-timestamp", true);
         src.set_property_from_str("device", &self.device_id);
 
         let queue = gst::ElementFactory::make("queue")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing queue element"))?;
+            .map_err(|_| io::Error::other("missing queue element"))?;
 
-        queue.set_property("max-size-time", &100_000_000u64);
+        queue.set_property("max-size-time", 100_000_000u64);
         queue.set_property_from_str("leaky", "downstream");
 
         let convert = gst::ElementFactory::make("audioconvert")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing audioconvert"))?;
+            .map_err(|_| io::Error::other("missing audioconvert"))?;
 
         let volume = gst::ElementFactory::make("volume")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing volume element"))?;
+            .map_err(|_| io::Error::other("missing volume element"))?;
         let volume_value = volume_value as f64;
-        volume.set_property("volume", &volume_value);
+        volume.set_property("volume", volume_value);
 
         pipeline
-            .add_many(&[&src, &convert, &volume, &queue])
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to add elements"))?;
-        gst::Element::link_many(&[&src, &convert, &volume, &queue])
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to link elements"))?;
+            .add_many([&src, &convert, &volume, &queue])
+            .map_err(|_| io::Error::other("failed to add elements"))?;
+        gst::Element::link_many([&src, &convert, &volume, &queue])
+            .map_err(|_| io::Error::other("failed to link elements"))?;
 
         Ok(AudioSourceOutput {
             element: queue,
@@ -1,4 +1,5 @@
 mod mic;
+#[allow(clippy::module_inception)]
 mod source;
 mod system;
 
@@ -21,24 +21,24 @@ impl SystemAudioSource {
     ) -> io::Result<AudioSourceOutput> {
         let src = gst::ElementFactory::make("wasapisrc")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing wasapisrc"))?;
+            .map_err(|_| io::Error::other("missing wasapisrc"))?;
 
-        src.set_property("loopback", &true);
-        src.set_property("do-timestamp", &true);
+        src.set_property("loopback", true);
+        src.set_property("do-timestamp", true);
 
         let convert = gst::ElementFactory::make("audioconvert")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing audioconvert"))?;
+            .map_err(|_| io::Error::other("missing audioconvert"))?;
 
         let resample = gst::ElementFactory::make("audioresample")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing audioresample"))?;
+            .map_err(|_| io::Error::other("missing audioresample"))?;
 
-        resample.set_property("quality", &10i32);
+        resample.set_property("quality", 10i32);
 
         let capsfilter = gst::ElementFactory::make("capsfilter")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing capsfilter"))?;
+            .map_err(|_| io::Error::other("missing capsfilter"))?;
 
         let caps = gst::Caps::builder("audio/x-raw")
             .field("rate", 48_000i32)
@@ -49,23 +49,23 @@ impl SystemAudioSource {
 
         let volume = gst::ElementFactory::make("volume")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing volume element"))?;
+            .map_err(|_| io::Error::other("missing volume element"))?;
         let volume_value = volume_value as f64;
-        volume.set_property("volume", &volume_value);
+        volume.set_property("volume", volume_value);
 
         let queue = gst::ElementFactory::make("queue")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing queue element"))?;
+            .map_err(|_| io::Error::other("missing queue element"))?;
 
-        queue.set_property("max-size-time", &100_000_000u64);
+        queue.set_property("max-size-time", 100_000_000u64);
         queue.set_property_from_str("leaky", "downstream");
 
         pipeline
-            .add_many(&[&src, &convert, &resample, &capsfilter, &volume, &queue])
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to add elements"))?;
+            .add_many([&src, &convert, &resample, &capsfilter, &volume, &queue])
+            .map_err(|_| io::Error::other("failed to add elements"))?;
 
-        gst::Element::link_many(&[&src, &convert, &resample, &capsfilter, &volume, &queue])
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to link elements"))?;
+        gst::Element::link_many([&src, &convert, &resample, &capsfilter, &volume, &queue])
+            .map_err(|_| io::Error::other("failed to link elements"))?;
 
         Ok(AudioSourceOutput {
             element: queue,
@@ -152,10 +152,6 @@ mod other {
         Vec::new()
     }
 
-    pub fn list_audio_devices() -> Vec<AudioDevice> {
-        Vec::new()
-    }
-
     pub fn list_microphone_devices() -> Vec<AudioDevice> {
         Vec::new()
     }
@@ -1,8 +1,8 @@
 use serde::Serialize;
 use std::io;
 
-use gstreamer as gst;
 use gst::prelude::GstObjectExt;
+use gstreamer as gst;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct VideoEncoderDescriptor {
@@ -13,10 +13,12 @@ pub struct VideoEncoderDescriptor {
 }
 
 pub fn list_video_encoders() -> io::Result<Vec<VideoEncoderDescriptor>> {
-    gst::init().map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+    gst::init().map_err(|err| io::Error::other(err.to_string()))?;
 
-    let factories =
-        gst::ElementFactory::factories_with_type(gst::ElementFactoryType::VIDEO_ENCODER, gst::Rank::NONE);
+    let factories = gst::ElementFactory::factories_with_type(
+        gst::ElementFactoryType::VIDEO_ENCODER,
+        gst::Rank::NONE,
+    );
 
     let mut encoders = Vec::new();
 
@@ -37,8 +39,8 @@ pub fn list_video_encoders() -> io::Result<Vec<VideoEncoderDescriptor>> {
         if required_memory.as_deref() == Some("D3D12Memory") {
             continue;
         }
-        let is_hardware =
-            factory.has_type(gst::ElementFactoryType::HARDWARE) || factory.klass().contains("Hardware");
+        let is_hardware = factory.has_type(gst::ElementFactoryType::HARDWARE)
+            || factory.klass().contains("Hardware");
 
         encoders.push(VideoEncoderDescriptor {
             id: factory_name.to_string(),
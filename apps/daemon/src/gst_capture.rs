@@ -1,26 +1,93 @@
 use std::{
+    collections::HashMap,
     io,
     sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
     },
+    time::{Duration, Instant},
 };
 
 use gst::prelude::*;
 use gstreamer as gst;
 use gstreamer_app as gst_app;
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use serde::Serialize;
 
 use crate::audio::{AudioGraph, AudioSourceId};
 use crate::video::VideoGraph;
 
 use crate::{
     logger,
-    ring_buffer::{Packet, RingBuffer},
+    ring_buffer::{Packet, RingBuffer, Snapshot, StreamId},
     settings::UserSettings,
 };
 
+// mpegtsmux uses the requested pad index as the PID, so pinning them lets
+// packets be attributed to a stream from the TS header alone.
+const VIDEO_PID: u16 = 0x41;
+const AUDIO_PID: u16 = 0x42;
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_NULL_PID: u16 = 0x1fff;
+const DROP_WARNING_INTERVAL: Duration = Duration::from_secs(1);
+// Bytes a `tap_packets` stream may send. Taps are unbounded channels so a
+// reader still busy with the pre-roll loses nothing; they end once the
+// post-roll is covered, and a maximum post-roll at 50 Mbps is about 750 MB.
+// Past this the tap is ended instead of growing without bound.
+const TAP_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Packet loss counters for the appsink -> ring buffer path.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CaptureStats {
+    pub packets_received: u64,
+    /// TS packets missing before the appsink callback (appsink `drop=true`),
+    /// detected from gaps in the TS continuity counters.
+    pub appsink_dropped_ts_packets: u64,
+    /// Packets thrown away because the worker channel was full.
+    pub channel_dropped_packets: u64,
+}
+
+#[derive(Default)]
+struct DropCounters {
+    packets_received: AtomicU64,
+    appsink_dropped_ts_packets: AtomicU64,
+    channel_dropped_packets: AtomicU64,
+    last_warning: Mutex<Option<Instant>>,
+}
+
+impl DropCounters {
+    fn stats(&self) -> CaptureStats {
+        CaptureStats {
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            appsink_dropped_ts_packets: self.appsink_dropped_ts_packets.load(Ordering::Relaxed),
+            channel_dropped_packets: self.channel_dropped_packets.load(Ordering::Relaxed),
+        }
+    }
+
+    // Drops tend to come in bursts, so warnings are rate limited.
+    fn warn(&self) {
+        let Ok(mut last) = self.last_warning.lock() else {
+            return;
+        };
+
+        if last.is_some_and(|at| at.elapsed() < DROP_WARNING_INTERVAL) {
+            return;
+        }
+        *last = Some(Instant::now());
+
+        let stats = self.stats();
+        logger::warn(
+            "capture",
+            format!(
+                "packets dropped (appsink: {} TS packets, channel: {} packets)",
+                stats.appsink_dropped_ts_packets, stats.channel_dropped_packets
+            ),
+        );
+    }
+}
+
 /// Capture core boundary:
 /// - Owns the GStreamer pipeline lifecycle and elements.
 /// - Emits encoded packets into the ring buffer.
@@ -50,6 +117,9 @@ pub struct GstCapture {
 
     // packet pipeline
     packet_tx: Option<Sender<Packet>>,
+    ring_buffer: Arc<Mutex<RingBuffer>>,
+    packet_taps: Arc<Mutex<Vec<PacketTap>>>,
+    drop_counters: Arc<DropCounters>,
 
     // audio controls
     system_volume: Option<gst::Element>,
@@ -60,7 +130,7 @@ impl GstCapture {
     pub fn start(config: &UserSettings, ring_buffer: Arc<Mutex<RingBuffer>>) -> io::Result<Self> {
         let callback_guard = Arc::new(AtomicBool::new(false));
 
-        gst::init().map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        gst::init().map_err(|err| io::Error::other(err.to_string()))?;
 
         validate_config(config)?;
 
@@ -73,12 +143,12 @@ impl GstCapture {
 
         pipeline
             .add(&mux)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to add mux"))?;
+            .map_err(|_| io::Error::other("Failed to add mux"))?;
 
         let video = VideoGraph::build(&pipeline, config)?;
         let audio = AudioGraph::build(&pipeline, config)?;
 
-        link_queue_to_mux(&video.output.element, &mux, "video")?;
+        link_queue_to_mux(&video.output.element, &mux, VIDEO_PID, "video")?;
 
         let (system_volume, mic_volume) = match audio.as_ref() {
             Some(graph) => (graph.volumes.system.clone(), graph.volumes.mic.clone()),
@@ -92,26 +162,26 @@ impl GstCapture {
             } else {
                 logger::warn("audio", "audio output has no src pad");
             }
-            link_queue_to_mux(&audio.output.element, &mux, "audio")?;
+            link_queue_to_mux(&audio.output.element, &mux, AUDIO_PID, "audio")?;
         }
 
         let appsink = make_element("appsink")?;
         let appsink = appsink
             .downcast::<gst_app::AppSink>()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "appsink downcast failed"))?;
+            .map_err(|_| io::Error::other("appsink downcast failed"))?;
 
-        appsink.set_property("emit-signals", &true);
-        appsink.set_property("sync", &false);
-        appsink.set_property("async", &false);
-        appsink.set_property("drop", &true);
-        appsink.set_property("max-buffers", &4u32);
+        appsink.set_property("emit-signals", true);
+        appsink.set_property("sync", false);
+        appsink.set_property("async", false);
+        appsink.set_property("drop", true);
+        appsink.set_property("max-buffers", 4u32);
 
         pipeline
             .add(&appsink)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to add appsink"))?;
+            .map_err(|_| io::Error::other("Failed to add appsink"))?;
 
         mux.link(&appsink)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to link mux to appsink"))?;
+            .map_err(|_| io::Error::other("failed to link mux to appsink"))?;
 
         video.attach_keyframe_tracker(ring_buffer.clone())?;
 
@@ -120,12 +190,22 @@ impl GstCapture {
         // Worker thread owns the ring buffer
         let ring_buffer_clone = ring_buffer.clone();
         let stop_flag_clone = stop_flag.clone();
+        let packet_taps: Arc<Mutex<Vec<PacketTap>>> = Arc::new(Mutex::new(Vec::new()));
+        let packet_taps_clone = packet_taps.clone();
 
         let worker_thread = std::thread::spawn(move || {
             while !stop_flag_clone.load(Ordering::SeqCst) {
                 match packet_rx.recv() {
                     Ok(packet) => {
                         if let Ok(mut rb) = ring_buffer_clone.lock() {
+                            let packet = rb.place_on_timeline(packet);
+
+                            // Taps are fed under the ring buffer lock so a tap's
+                            // snapshot and its packet stream never overlap.
+                            // Feeding a tap never blocks.
+                            if let Ok(mut taps) = packet_taps_clone.lock() {
+                                taps.retain_mut(|tap| tap.feed(&packet));
+                            }
                             rb.push(packet);
                         }
                     }
@@ -135,6 +215,11 @@ impl GstCapture {
         });
 
         let tx = packet_tx.clone();
+        let drop_counters = Arc::new(DropCounters::default());
+        let counters = drop_counters.clone();
+        let continuity = Mutex::new(HashMap::new());
+        // Set after a dropped packet so the next one is pushed as a discontinuity.
+        let pending_discont = AtomicBool::new(false);
 
         appsink.set_callbacks(
             gst_app::AppSinkCallbacks::builder()
@@ -142,16 +227,49 @@ impl GstCapture {
                     let sample = sink.pull_sample().map_err(|_| gst::FlowError::Error)?;
                     let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
 
-                    let pts_ms = buffer.dts_or_pts().map(|pts| pts.mseconds()).unwrap_or(0);
+                    let dts_ns = buffer.dts_or_pts().map(|ts| ts.nseconds()).unwrap_or(0);
+                    let pts_ns = buffer.pts().map(|ts| ts.nseconds()).unwrap_or(dts_ns);
+                    let flags = buffer.flags();
 
                     if let Ok(map) = buffer.map_readable() {
+                        let data = map.as_slice();
+                        counters.packets_received.fetch_add(1, Ordering::Relaxed);
+
+                        let lost = continuity
+                            .lock()
+                            .map(|mut continuity| count_lost_ts_packets(&mut continuity, data))
+                            .unwrap_or(0);
+                        if lost > 0 {
+                            counters
+                                .appsink_dropped_ts_packets
+                                .fetch_add(lost, Ordering::Relaxed);
+                            counters.warn();
+                        }
+
+                        let discont = flags.contains(gst::BufferFlags::DISCONT)
+                            || lost > 0
+                            || pending_discont.swap(false, Ordering::SeqCst);
+
                         let packet = Packet {
-                            pts_ms,
-                            data: map.as_slice().to_vec(),
+                            stream_id: stream_id_for(data),
+                            pts_ns,
+                            dts_ns,
+                            duration_ns: buffer.duration().map(|d| d.nseconds()),
+                            keyframe: !flags.contains(gst::BufferFlags::DELTA_UNIT),
+                            discont,
+                            // The only copy a packet's payload sees; everything
+                            // downstream shares this allocation.
+                            data: Arc::from(data),
                         };
 
-                        // Non blocking send, if full: drop.
-                        let _ = tx.try_send(packet);
+                        // Non blocking send, if full: drop and flag the gap.
+                        if let Err(TrySendError::Full(_)) = tx.try_send(packet) {
+                            counters
+                                .channel_dropped_packets
+                                .fetch_add(1, Ordering::Relaxed);
+                            pending_discont.store(true, Ordering::SeqCst);
+                            counters.warn();
+                        }
                     }
 
                     Ok(gst::FlowSuccess::Ok)
@@ -165,10 +283,7 @@ impl GstCapture {
                 &state,
                 CaptureState::Failed("failed to start GStreamer pipeline".to_string()),
             );
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "failed to start GStreamer pipeline",
-            ));
+            return Err(io::Error::other("failed to start GStreamer pipeline"));
         }
 
         set_state(&state, CaptureState::Running);
@@ -192,6 +307,9 @@ impl GstCapture {
             worker_thread: Some(worker_thread),
 
             packet_tx: Some(packet_tx),
+            ring_buffer,
+            packet_taps,
+            drop_counters,
 
             system_volume,
             mic_volume,
@@ -206,10 +324,42 @@ impl GstCapture {
         self.state.lock().unwrap().clone()
     }
 
+    pub fn stats(&self) -> CaptureStats {
+        self.drop_counters.stats()
+    }
+
     pub fn stop(&mut self) {
         self.stop_inner();
     }
 
+    /// Snapshots the replay buffer and starts streaming the following `post_roll_ms`
+    /// of packets to the returned receiver. The stream ends early when the receiver
+    /// is dropped, capture stops, or it passes `TAP_MAX_BYTES`.
+    /// `pre_roll_ms` limits the snapshot to the last N ms; `None` takes the whole buffer.
+    /// Spilled pre-roll payloads are only read as the snapshot is iterated.
+    pub fn tap_packets(
+        &self,
+        pre_roll_ms: Option<u64>,
+        post_roll_ms: u64,
+    ) -> (Snapshot, Receiver<Packet>) {
+        let (sender, rx) = crossbeam_channel::unbounded();
+        let rb = self.ring_buffer.lock().unwrap();
+
+        let pre_roll = match pre_roll_ms {
+            Some(ms) => rb.stream_last(ms),
+            None => rb.stream_from_keyframe(),
+        };
+        self.packet_taps.lock().unwrap().push(PacketTap {
+            sender,
+            start_dts_ns: pre_roll.dts_range().map(|(_, last)| last),
+            duration_ns: post_roll_ms.saturating_mul(1_000_000),
+            sent_bytes: 0,
+            max_bytes: TAP_MAX_BYTES,
+        });
+
+        (pre_roll, rx)
+    }
+
     pub fn volume_element(&self, source: AudioSourceId) -> Option<gst::Element> {
         match source {
             AudioSourceId::System => self.system_volume.clone(),
@@ -220,7 +370,7 @@ impl GstCapture {
     pub fn set_volume(&self, source: AudioSourceId, value: f32) -> bool {
         if let Some(element) = self.volume_element(source) {
             let value = value as f64;
-            element.set_property("volume", &value);
+            element.set_property("volume", value);
             return true;
         }
         false
@@ -242,7 +392,7 @@ impl GstCapture {
         self.callback_guard.store(true, Ordering::SeqCst);
         self.appsink
             .set_callbacks(gst_app::AppSinkCallbacks::builder().build());
-        self.appsink.set_property("emit-signals", &false);
+        self.appsink.set_property("emit-signals", false);
 
         // 2) Signal threads to stop
         self.stop_flag.store(true, Ordering::SeqCst);
@@ -251,6 +401,9 @@ impl GstCapture {
         if let Some(sender) = self.packet_tx.take() {
             drop(sender);
         }
+        if let Ok(mut taps) = self.packet_taps.lock() {
+            taps.clear();
+        }
 
         // 4) Flush pipeline
         let _ = self.pipeline.send_event(gst::event::Eos::new());
@@ -279,27 +432,131 @@ impl Drop for GstCapture {
 fn make_element(name: &str) -> io::Result<gst::Element> {
     gst::ElementFactory::make(name)
         .build()
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, format!("missing element {}", name)))
+        .map_err(|_| io::Error::other(format!("missing element {}", name)))
 }
 
-fn link_queue_to_mux(queue: &gst::Element, mux: &gst::Element, label: &str) -> io::Result<()> {
+fn link_queue_to_mux(
+    queue: &gst::Element,
+    mux: &gst::Element,
+    pid: u16,
+    label: &str,
+) -> io::Result<()> {
     let queue_src = queue
         .static_pad("src")
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing queue src pad"))?;
+        .ok_or_else(|| io::Error::other("missing queue src pad"))?;
     let mux_sink = mux
-        .request_pad_simple("sink_%d")
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing mux sink pad"))?;
+        .request_pad_simple(&format!("sink_{}", pid))
+        .ok_or_else(|| io::Error::other("missing mux sink pad"))?;
 
-    queue_src.link(&mux_sink).map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("failed to link {} to mux", label),
-        )
-    })?;
+    queue_src
+        .link(&mux_sink)
+        .map_err(|_| io::Error::other(format!("failed to link {} to mux", label)))?;
 
     Ok(())
 }
 
+// A `tap_packets` stream, fed by the worker thread until it covers
+// `duration_ns` past `start_dts_ns` (the newest packet at the tap).
+struct PacketTap {
+    sender: Sender<Packet>,
+    start_dts_ns: Option<u64>,
+    duration_ns: u64,
+    sent_bytes: u64,
+    max_bytes: u64,
+}
+
+impl PacketTap {
+    // Sends `packet` without blocking; false once the tap is done with.
+    fn feed(&mut self, packet: &Packet) -> bool {
+        let start = *self.start_dts_ns.get_or_insert(packet.dts_ns);
+        let covered_ns = packet.dts_ns.saturating_sub(start);
+
+        self.sent_bytes += packet.data.len() as u64;
+        if self.sent_bytes > self.max_bytes {
+            logger::warn(
+                "capture",
+                format!(
+                    "post-roll passed {} MiB after {} of {} ms, ending it early",
+                    self.max_bytes / (1024 * 1024),
+                    covered_ns / 1_000_000,
+                    self.duration_ns / 1_000_000
+                ),
+            );
+            return false;
+        }
+
+        self.sender.send(packet.clone()).is_ok() && covered_ns < self.duration_ns
+    }
+}
+
+// Classifies a muxer buffer by the elementary streams it carries. A buffer can
+// hold several TS packets and often starts with PAT/PMT, so every packet is
+// looked at; video wins so keyframe buffers stay video.
+fn stream_id_for(data: &[u8]) -> StreamId {
+    let mut stream_id = StreamId::Other;
+
+    for ts in data.chunks(TS_PACKET_SIZE) {
+        // TS header: sync byte, then 13-bit PID across bytes 1-2.
+        if ts.len() < 3 || ts[0] != 0x47 {
+            continue;
+        }
+
+        match ts_pid(ts) {
+            VIDEO_PID => return StreamId::Video,
+            AUDIO_PID => stream_id = StreamId::Audio,
+            _ => {}
+        }
+    }
+
+    stream_id
+}
+
+// Counts TS packets missing according to the 4-bit continuity counter of each PID.
+// The counter only advances on packets that carry a payload, and a repeat is a
+// legal duplicate, so both are skipped. A packet with the adaptation field's
+// discontinuity_indicator set may restart the counter anywhere.
+fn count_lost_ts_packets(continuity: &mut HashMap<u16, u8>, data: &[u8]) -> u64 {
+    let mut lost = 0u64;
+
+    for ts in data.chunks_exact(TS_PACKET_SIZE) {
+        if ts[0] != 0x47 {
+            continue;
+        }
+
+        let pid = ts_pid(ts);
+        let has_payload = ts[3] & 0x10 != 0;
+        if pid == TS_NULL_PID || !has_payload {
+            continue;
+        }
+
+        let counter = ts[3] & 0x0f;
+        let previous = continuity.insert(pid, counter);
+
+        if has_discontinuity_indicator(ts) {
+            continue;
+        }
+
+        if let Some(previous) = previous {
+            let expected = (previous + 1) & 0x0f;
+            if counter != expected && counter != previous {
+                lost += u64::from(counter.wrapping_sub(expected) & 0x0f);
+            }
+        }
+    }
+
+    lost
+}
+
+fn ts_pid(ts: &[u8]) -> u16 {
+    (u16::from(ts[1] & 0x1f) << 8) | u16::from(ts[2])
+}
+
+// Adaptation field present and non-empty, with its first flag bit set.
+fn has_discontinuity_indicator(ts: &[u8]) -> bool {
+    let has_adaptation_field = ts[3] & 0x20 != 0;
+    has_adaptation_field && ts[4] > 0 && ts[5] & 0x80 != 0
+}
+
 fn validate_config(config: &UserSettings) -> io::Result<()> {
     if let Some(mic_id) = &config.mic_device_id {
         if mic_id.is_empty() {
@@ -327,7 +584,7 @@ fn spawn_bus_thread(
 ) -> io::Result<std::thread::JoinHandle<()>> {
     let bus = pipeline
         .bus()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing pipeline bus"))?;
+        .ok_or_else(|| io::Error::other("missing pipeline bus"))?;
     let pipeline_name = pipeline.name();
 
     let handle = std::thread::spawn(move || {
@@ -361,17 +618,16 @@ fn spawn_bus_thread(
                         logger::debug("gst", format!("debug: {}", debug));
                     }
                 }
-                gst::MessageView::StateChanged(state) => {
+                gst::MessageView::StateChanged(state)
                     if message
                         .src()
                         .map(|s| s.name() == pipeline_name)
-                        .unwrap_or(false)
-                    {
-                        logger::info(
-                            "gst",
-                            format!("pipeline state: {:?} -> {:?}", state.old(), state.current()),
-                        );
-                    }
+                        .unwrap_or(false) =>
+                {
+                    logger::info(
+                        "gst",
+                        format!("pipeline state: {:?} -> {:?}", state.old(), state.current()),
+                    );
                 }
                 gst::MessageView::Eos(..) => {
                     logger::error("gst", "eos");
@@ -388,6 +644,172 @@ fn spawn_bus_thread(
 
 fn set_bool_property(element: &gst::Element, name: &str, value: bool) {
     if element.find_property(name).is_some() {
-        element.set_property(name, &value);
+        element.set_property(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One TS packet for `pid` with continuity counter `counter`.
+    fn ts_packet(pid: u16, counter: u8, payload: bool) -> Vec<u8> {
+        let mut ts = vec![0xff; TS_PACKET_SIZE];
+        ts[0] = 0x47;
+        ts[1] = (pid >> 8) as u8 & 0x1f;
+        ts[2] = pid as u8;
+        ts[3] = if payload { 0x10 } else { 0x20 } | (counter & 0x0f);
+        if !payload {
+            ts[4] = 183;
+            ts[5] = 0;
+        }
+        ts
+    }
+
+    fn with_discontinuity_indicator(mut ts: Vec<u8>) -> Vec<u8> {
+        ts[3] |= 0x20;
+        ts[4] = 1;
+        ts[5] = 0x80;
+        ts
+    }
+
+    fn lost(continuity: &mut HashMap<u16, u8>, packets: &[Vec<u8>]) -> u64 {
+        count_lost_ts_packets(continuity, &packets.concat())
+    }
+
+    #[test]
+    fn counts_skipped_continuity_counters_per_pid() {
+        let mut continuity = HashMap::new();
+        let packets = [
+            ts_packet(VIDEO_PID, 0, true),
+            ts_packet(AUDIO_PID, 7, true),
+            ts_packet(VIDEO_PID, 1, true),
+            ts_packet(VIDEO_PID, 4, true),
+            ts_packet(AUDIO_PID, 8, true),
+        ];
+
+        assert_eq!(lost(&mut continuity, &packets), 2);
+    }
+
+    #[test]
+    fn continuity_counter_wraps_around() {
+        let mut continuity = HashMap::new();
+        let in_order = [
+            ts_packet(VIDEO_PID, 14, true),
+            ts_packet(VIDEO_PID, 15, true),
+            ts_packet(VIDEO_PID, 0, true),
+        ];
+        assert_eq!(lost(&mut continuity, &in_order), 0);
+
+        // 0 -> 2 across buffers skips 1.
+        assert_eq!(lost(&mut continuity, &[ts_packet(VIDEO_PID, 2, true)]), 1);
+        // 2 -> 1 wraps: 3..=15 and 0 are gone.
+        assert_eq!(lost(&mut continuity, &[ts_packet(VIDEO_PID, 1, true)]), 14);
+    }
+
+    #[test]
+    fn duplicates_and_packets_without_payload_are_not_losses() {
+        let mut continuity = HashMap::new();
+        let packets = [
+            ts_packet(VIDEO_PID, 3, true),
+            ts_packet(VIDEO_PID, 3, true),
+            // Adaptation-only packets keep the counter where it was.
+            ts_packet(VIDEO_PID, 9, false),
+            ts_packet(VIDEO_PID, 4, true),
+            ts_packet(TS_NULL_PID, 12, true),
+            ts_packet(TS_NULL_PID, 2, true),
+        ];
+
+        assert_eq!(lost(&mut continuity, &packets), 0);
+    }
+
+    #[test]
+    fn discontinuity_indicator_restarts_the_counter() {
+        let mut continuity = HashMap::new();
+        let packets = [
+            ts_packet(VIDEO_PID, 3, true),
+            with_discontinuity_indicator(ts_packet(VIDEO_PID, 11, true)),
+            ts_packet(VIDEO_PID, 12, true),
+            ts_packet(VIDEO_PID, 14, true),
+        ];
+
+        assert_eq!(lost(&mut continuity, &packets), 1);
+    }
+
+    #[test]
+    fn classifies_buffers_by_any_of_their_packets() {
+        let pat = ts_packet(0, 0, true);
+        let pmt = ts_packet(0x1000, 0, true);
+
+        assert_eq!(
+            stream_id_for(&[pat.clone(), pmt.clone(), ts_packet(VIDEO_PID, 0, true)].concat()),
+            StreamId::Video
+        );
+        assert_eq!(
+            stream_id_for(&[pat.clone(), ts_packet(AUDIO_PID, 0, true)].concat()),
+            StreamId::Audio
+        );
+        assert_eq!(
+            stream_id_for(&[ts_packet(AUDIO_PID, 0, true), ts_packet(VIDEO_PID, 0, true)].concat()),
+            StreamId::Video
+        );
+        assert_eq!(stream_id_for(&[pat, pmt].concat()), StreamId::Other);
+        assert_eq!(stream_id_for(&[0x00; 4]), StreamId::Other);
+    }
+
+    fn tapped(dts_ms: u64) -> Packet {
+        Packet {
+            stream_id: StreamId::Video,
+            pts_ns: dts_ms * 1_000_000,
+            dts_ns: dts_ms * 1_000_000,
+            duration_ns: None,
+            keyframe: false,
+            discont: false,
+            data: vec![0; TS_PACKET_SIZE].into(),
+        }
+    }
+
+    fn tap(duration_ms: u64, max_bytes: u64) -> (PacketTap, Receiver<Packet>) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let tap = PacketTap {
+            sender,
+            start_dts_ns: Some(0),
+            duration_ns: duration_ms * 1_000_000,
+            sent_bytes: 0,
+            max_bytes,
+        };
+        (tap, receiver)
+    }
+
+    #[test]
+    fn tap_queues_a_whole_post_roll_for_a_slow_reader() {
+        let (mut tap, receiver) = tap(30_000, TAP_MAX_BYTES);
+
+        // 30 s of single TS packet buffers at 20 Mbps, none of them read yet.
+        let count = 20_000_000 / 8 / TS_PACKET_SIZE as u64 * 30;
+        let packet = tapped(0);
+        for index in 0..count {
+            let packet = Packet {
+                dts_ns: index * 30_000_000_000 / count,
+                ..packet.clone()
+            };
+            assert!(tap.feed(&packet));
+        }
+
+        assert_eq!(receiver.len() as u64, count);
+        // The first packet past the post-roll is the last one sent.
+        assert!(!tap.feed(&tapped(30_000)));
+        assert_eq!(receiver.len() as u64, count + 1);
+    }
+
+    #[test]
+    fn tap_ends_past_its_byte_budget() {
+        let (mut tap, receiver) = tap(60_000, 3 * TS_PACKET_SIZE as u64);
+
+        assert!(tap.feed(&tapped(0)));
+        assert!(tap.feed(&tapped(10)));
+        assert!(tap.feed(&tapped(20)));
+        assert!(!tap.feed(&tapped(30)));
+        assert_eq!(receiver.len(), 3);
     }
 }
@@ -9,10 +9,11 @@ pub trait GstLinkExt {
 impl GstLinkExt for gst::Element {
     fn link_io(&self, other: &gst::Element) -> io::Result<()> {
         self.link(other).map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("failed to link {} -> {}", self.name(), other.name()),
-            )
+            io::Error::other(format!(
+                "failed to link {} -> {}",
+                self.name(),
+                other.name()
+            ))
         })
     }
 }
@@ -4,7 +4,9 @@ pub mod encoders;
 pub mod gst_capture;
 pub mod gst_utils;
 pub mod logger;
+pub mod post_roll;
 pub mod remux;
 pub mod ring_buffer;
 pub mod settings;
+pub mod spill;
 pub mod video;
@@ -0,0 +1,262 @@
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+
+use crate::{
+    logger,
+    remux::CancelToken,
+    ring_buffer::{Packet, Snapshot},
+};
+
+// Extra wall-clock time allowed for packets stuck in the encoder and mux queues.
+const POST_ROLL_GRACE: Duration = Duration::from_secs(2);
+// How often a post-roll waiting on capture checks for cancellation.
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// A clip save held open after the hotkey press, as one packet stream:
+/// - Yields the pre-roll snapshot taken at the press, read lazily.
+/// - Then yields packets streamed from `GstCapture::tap_packets` until the
+///   post-roll has been covered on the packet timeline, capture stops, the
+///   wall-clock deadline passes, or `cancel` is cancelled.
+/// - Meant to be fed straight into `RemuxJob::run_stream` on its own thread;
+///   the capture worker only ever does a non-blocking send.
+pub struct PostRoll<F> {
+    pre_roll: Snapshot,
+    receiver: Receiver<Packet>,
+    duration_ns: u64,
+    deadline: Instant,
+    press_dts: Option<u64>,
+    covered_ns: u64,
+    reported: Option<f32>,
+    finished: bool,
+    cancel: Option<CancelToken>,
+    on_progress: F,
+}
+
+impl<F: FnMut(f32)> PostRoll<F> {
+    /// `on_progress` receives the covered post-roll fraction in 0.0..=1.0 as
+    /// the stream gets past the pre-roll.
+    pub fn new(
+        pre_roll: Snapshot,
+        receiver: Receiver<Packet>,
+        duration_ms: u64,
+        cancel: Option<CancelToken>,
+        on_progress: F,
+    ) -> Self {
+        let duration_ns = duration_ms.saturating_mul(1_000_000);
+
+        Self {
+            press_dts: pre_roll.dts_range().map(|(_, last)| last),
+            pre_roll,
+            receiver,
+            duration_ns,
+            covered_ns: 0,
+            deadline: Instant::now() + Duration::from_nanos(duration_ns) + POST_ROLL_GRACE,
+            reported: None,
+            finished: false,
+            cancel,
+            on_progress,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        // Dropping the receiver removes the tap from the capture worker.
+        self.receiver = crossbeam_channel::never();
+        (self.on_progress)(1.0);
+    }
+}
+
+impl<F: FnMut(f32)> Iterator for PostRoll<F> {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        if let Some(packet) = self.pre_roll.next() {
+            return Some(packet);
+        }
+
+        if self.finished {
+            return None;
+        }
+
+        if self.reported.is_none() {
+            self.reported = Some(0.0);
+            (self.on_progress)(0.0);
+        }
+
+        let packet = loop {
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                self.finish();
+                return None;
+            }
+
+            // Packets queued while the pre-roll was being read still come out
+            // after the deadline; only an empty channel times out.
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+
+            match self.receiver.recv_timeout(remaining.min(CANCEL_POLL)) {
+                Ok(packet) => break packet,
+                Err(RecvTimeoutError::Timeout) if remaining > CANCEL_POLL => {}
+                Err(_) => {
+                    // Capture stopped, the tap overflowed, or nothing came in time.
+                    if self.covered_ns < self.duration_ns {
+                        logger::warn(
+                            "capture",
+                            format!(
+                                "post-roll ended after {} of {} ms",
+                                self.covered_ns / 1_000_000,
+                                self.duration_ns / 1_000_000
+                            ),
+                        );
+                    }
+                    self.finish();
+                    return None;
+                }
+            }
+        };
+
+        let start = *self.press_dts.get_or_insert(packet.dts_ns);
+        let covered = packet.dts_ns.saturating_sub(start);
+        self.covered_ns = covered;
+
+        if covered >= self.duration_ns {
+            self.finish();
+            return Some(packet);
+        }
+
+        let progress = covered as f32 / self.duration_ns.max(1) as f32;
+        if self
+            .reported
+            .is_some_and(|reported| progress - reported >= 0.05)
+        {
+            self.reported = Some(progress);
+            (self.on_progress)(progress);
+        }
+
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring_buffer::{RingBuffer, StreamId};
+
+    fn packet(dts_ms: u64) -> Packet {
+        Packet {
+            stream_id: StreamId::Video,
+            pts_ns: dts_ms * 1_000_000,
+            dts_ns: dts_ms * 1_000_000,
+            duration_ns: None,
+            keyframe: dts_ms == 0,
+            discont: false,
+            data: vec![0; 188].into(),
+        }
+    }
+
+    fn pre_roll(dts_ms: &[u64]) -> Snapshot {
+        let mut buffer = RingBuffer::new(60_000);
+        for dts_ms in dts_ms {
+            buffer.push(packet(*dts_ms));
+        }
+        buffer.stream()
+    }
+
+    fn dts_ms(packets: Vec<Packet>) -> Vec<u64> {
+        packets
+            .iter()
+            .map(|packet| packet.dts_ns / 1_000_000)
+            .collect()
+    }
+
+    #[test]
+    fn yields_pre_roll_then_post_roll_until_covered() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        for dts in [1100, 1500, 2000, 2500] {
+            sender.send(packet(dts)).unwrap();
+        }
+
+        let mut progress = Vec::new();
+        let packets: Vec<Packet> =
+            PostRoll::new(pre_roll(&[0, 500, 1000]), receiver, 1000, None, |value| {
+                progress.push(value)
+            })
+            .collect();
+
+        // Stops at the first packet 1 s past the press, with the sender still open.
+        assert_eq!(dts_ms(packets), vec![0, 500, 1000, 1100, 1500, 2000]);
+        assert_eq!(progress.first(), Some(&0.0));
+        assert_eq!(progress.last(), Some(&1.0));
+        assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]));
+        // The receiver is dropped once the post-roll is covered.
+        assert!(sender.send(packet(3000)).is_err());
+    }
+
+    #[test]
+    fn ends_when_capture_stops() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        sender.send(packet(1100)).unwrap();
+        drop(sender);
+
+        let started = Instant::now();
+        let packets: Vec<Packet> =
+            PostRoll::new(pre_roll(&[0, 1000]), receiver, 5000, None, |_| {}).collect();
+
+        assert_eq!(dts_ms(packets), vec![0, 1000, 1100]);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn ends_at_the_deadline_without_packets() {
+        let (_sender, receiver) = crossbeam_channel::unbounded();
+
+        let started = Instant::now();
+        let mut progress = Vec::new();
+        let packets: Vec<Packet> = PostRoll::new(pre_roll(&[0]), receiver, 0, None, |value| {
+            progress.push(value)
+        })
+        .collect();
+
+        assert_eq!(dts_ms(packets), vec![0]);
+        assert!(started.elapsed() >= POST_ROLL_GRACE);
+        assert_eq!(progress, vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn first_tapped_packet_starts_the_post_roll_without_a_pre_roll() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        for dts in [5000, 5400, 5800] {
+            sender.send(packet(dts)).unwrap();
+        }
+
+        let packets: Vec<Packet> =
+            PostRoll::new(pre_roll(&[]), receiver, 400, None, |_| {}).collect();
+
+        assert_eq!(dts_ms(packets), vec![5000, 5400]);
+    }
+
+    #[test]
+    fn cancelling_stops_a_waiting_post_roll() {
+        let (_sender, receiver) = crossbeam_channel::unbounded();
+        let cancel = CancelToken::new();
+
+        let mut post_roll = PostRoll::new(
+            pre_roll(&[0]),
+            receiver,
+            60_000,
+            Some(cancel.clone()),
+            |_| {},
+        );
+        assert_eq!(post_roll.next().map(|packet| packet.dts_ns), Some(0));
+
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+
+        let started = Instant::now();
+        assert!(post_roll.next().is_none());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(post_roll.next().is_none());
+    }
+}
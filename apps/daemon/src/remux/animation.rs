@@ -0,0 +1,372 @@
+use std::{fs, path::Path};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    file_size, make_ts_appsrc, pad_kind, push_packets, read_sidecar,
+    trim::{attach_range_probe, index_clip},
+    watch_pipeline, write_sidecar_if_needed, CancelToken, ClipSidecar, RemuxResult,
+};
+use crate::{gst_utils, ring_buffer::Snapshot};
+
+// GIF frame delays are in 1/100 s, so anything faster just repeats delays.
+const MAX_FPS: u32 = 50;
+const MIN_WIDTH: u32 = 16;
+// gifenc trades palette quality for speed on a 1 (best) ..= 30 scale.
+const GIF_SLOWEST_SPEED: i32 = 1;
+const GIF_FASTEST_SPEED: i32 = 30;
+
+/// Animated image container for short reaction / bug report exports.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    Webp,
+}
+
+impl AnimationFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Settings for `export_animation`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimationExport {
+    #[serde(default)]
+    pub format: AnimationFormat,
+    pub fps: u32,
+    /// Output width in pixels; height follows the aspect ratio.
+    pub width: u32,
+    /// 1 (smallest file) ..= 100 (best palette / encoding quality).
+    pub quality: u8,
+}
+
+/// Where the frames of an animation come from.
+pub enum AnimationSource<'a> {
+    /// `[start_ms, end_ms)` of a saved clip, measured from its first video frame.
+    Clip {
+        path: &'a Path,
+        start_ms: u64,
+        end_ms: u64,
+    },
+    /// Ring buffer packets from `RingBuffer::stream_window`, read as they're
+    /// pushed.
+    Packets(Snapshot),
+}
+
+/// Decodes the source and writes an animated GIF or WebP to `output_path`.
+/// The partial output is removed on failure or cancellation. A clip's
+/// metadata goes to the sidecar, since neither format carries tags.
+pub fn export_animation(
+    source: AnimationSource,
+    output_path: &Path,
+    export: &AnimationExport,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(f32),
+) -> Result<RemuxResult, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    validate(&source, export)?;
+
+    let pipeline = gst::Pipeline::new();
+
+    // --- elements ---
+
+    let decodebin = gst_utils::make("decodebin")?;
+    let queue = gst_utils::make("queue")?;
+    let convert = gst_utils::make("videoconvert")?;
+    let videorate = gst_utils::make("videorate")?;
+    let videoscale = gst_utils::make("videoscale")?;
+    let capsfilter = gst_utils::make("capsfilter")?;
+    let encoder_convert = gst_utils::make("videoconvert")?;
+    let encoder = make_encoder(export)?;
+    let filesink = gst_utils::make("filesink")?;
+
+    // --- config ---
+
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", (export.width & !1) as i32)
+        .field("framerate", gst::Fraction::new(export.fps as i32, 1))
+        .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+        .build();
+    capsfilter.set_property("caps", &caps);
+
+    let location = output_path
+        .to_str()
+        .ok_or("output path is not valid UTF-8")?;
+    filesink.set_property("location", location);
+
+    // --- pipeline assembly ---
+
+    let chain = [
+        &queue,
+        &convert,
+        &videorate,
+        &videoscale,
+        &capsfilter,
+        &encoder_convert,
+        &encoder,
+        &filesink,
+    ];
+
+    pipeline.add(&decodebin).map_err(gst_utils::err)?;
+    pipeline.add_many(chain).map_err(gst_utils::err)?;
+    gst::Element::link_many(chain).map_err(gst_utils::err)?;
+
+    let queue_sink = queue.static_pad("sink").ok_or("missing queue sink pad")?;
+
+    // Decoded audio goes to a fakesink added on demand, so clips without audio
+    // don't leave a sink waiting for EOS.
+    let pipeline_weak = pipeline.downgrade();
+    let video_sink = queue_sink.clone();
+
+    decodebin.connect_pad_added(move |_, src_pad| match pad_kind(src_pad) {
+        Some("video") if !video_sink.is_linked() => {
+            let _ = src_pad.link(&video_sink);
+        }
+        _ => {
+            let Some(pipeline) = pipeline_weak.upgrade() else {
+                return;
+            };
+            let Ok(fakesink) = gst_utils::make("fakesink") else {
+                return;
+            };
+
+            fakesink.set_property("sync", false);
+
+            if pipeline.add(&fakesink).is_ok() && fakesink.sync_state_with_parent().is_ok() {
+                if let Some(sink) = fakesink.static_pad("sink") {
+                    let _ = src_pad.link(&sink);
+                }
+            }
+        }
+    });
+
+    // --- source ---
+
+    let metadata = match &source {
+        AnimationSource::Clip { path, .. } => {
+            read_sidecar(path).and_then(|sidecar| sidecar.metadata)
+        }
+        AnimationSource::Packets(_) => None,
+    };
+
+    let (duration_ms, packets) = match source {
+        AnimationSource::Clip {
+            path,
+            start_ms,
+            end_ms,
+        } => {
+            let origin_ns = index_clip(path)?.origin_ns.unwrap_or(0);
+            let from_ns = origin_ns + start_ms * 1_000_000;
+            attach_range_probe(
+                &queue_sink,
+                from_ns,
+                origin_ns + end_ms * 1_000_000,
+                Some(from_ns),
+            );
+
+            let filesrc = gst_utils::make("filesrc")?;
+            let input = path.to_str().ok_or("input path is not valid UTF-8")?;
+            filesrc.set_property("location", input);
+
+            pipeline.add(&filesrc).map_err(gst_utils::err)?;
+            filesrc.link(&decodebin).map_err(gst_utils::err)?;
+
+            (end_ms - start_ms, None)
+        }
+        AnimationSource::Packets(packets) => {
+            let Some((first_dts_ns, last_dts_ns)) = packets.dts_range() else {
+                return Err("no packets to export".to_string());
+            };
+
+            let appsrc = make_ts_appsrc()?;
+            pipeline
+                .add(appsrc.upcast_ref::<gst::Element>())
+                .map_err(gst_utils::err)?;
+            appsrc.link(&decodebin).map_err(gst_utils::err)?;
+
+            (
+                (last_dts_ns - first_dts_ns) / 1_000_000,
+                Some((appsrc, packets)),
+            )
+        }
+    };
+
+    // --- run ---
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    on_progress(0.0);
+
+    let pushed = match packets {
+        Some((appsrc, mut packets)) => {
+            push_packets(&appsrc, &pipeline, &mut packets, Some(cancel), &mut |_| {})
+        }
+        None => Ok(()),
+    };
+
+    let result = match pushed {
+        Ok(()) => watch_pipeline(&pipeline, Some(cancel), &mut on_progress),
+        Err(err) => {
+            pipeline.set_state(gst::State::Null).ok();
+            Err(err)
+        }
+    };
+
+    if let Err(err) = result {
+        let _ = fs::remove_file(output_path);
+        return Err(err);
+    }
+
+    write_sidecar_if_needed(
+        output_path,
+        ClipSidecar {
+            markers: Vec::new(),
+            metadata,
+        },
+    )?;
+
+    on_progress(1.0);
+
+    Ok(RemuxResult {
+        duration_ms,
+        bytes_written: file_size(output_path)?,
+        pushed_bytes: 0,
+    })
+}
+
+fn validate(source: &AnimationSource, export: &AnimationExport) -> Result<(), String> {
+    if export.fps == 0 || export.fps > MAX_FPS {
+        return Err(format!("frame rate must be between 1 and {}", MAX_FPS));
+    }
+    if export.width < MIN_WIDTH {
+        return Err(format!("width must be at least {} pixels", MIN_WIDTH));
+    }
+
+    match source {
+        AnimationSource::Clip {
+            start_ms, end_ms, ..
+        } if end_ms <= start_ms => Err("animation end must be after its start".to_string()),
+        AnimationSource::Packets(packets) if packets.is_empty() => {
+            Err("no packets to export".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+// Maps quality 1 ..= 100 onto gifenc's speed, fastest to slowest.
+fn gif_speed(quality: u8) -> i32 {
+    let quality = quality.clamp(1, 100);
+    let range = GIF_FASTEST_SPEED - GIF_SLOWEST_SPEED;
+
+    GIF_FASTEST_SPEED - range * i32::from(quality - 1) / 99
+}
+
+fn make_encoder(export: &AnimationExport) -> Result<gst::Element, String> {
+    match export.format {
+        AnimationFormat::Gif => {
+            let encoder = gst_utils::make("gifenc")?;
+
+            if encoder.find_property("speed").is_some() {
+                encoder.set_property("speed", gif_speed(export.quality));
+            }
+            if encoder.find_property("repeat").is_some() {
+                // -1 loops forever; 0 plays once.
+                encoder.set_property("repeat", -1i32);
+            }
+            Ok(encoder)
+        }
+        AnimationFormat::Webp => {
+            let encoder = gst_utils::make("webpenc")?;
+
+            encoder.set_property("animated", true);
+            encoder.set_property("quality", f32::from(export.quality.clamp(1, 100)));
+            Ok(encoder)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring_buffer::RingBuffer;
+
+    fn export(fps: u32, width: u32) -> AnimationExport {
+        AnimationExport {
+            format: AnimationFormat::Gif,
+            fps,
+            width,
+            quality: 80,
+        }
+    }
+
+    fn clip(start_ms: u64, end_ms: u64) -> AnimationSource<'static> {
+        AnimationSource::Clip {
+            path: Path::new("clip.mp4"),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    #[test]
+    fn validate_accepts_a_sane_export() {
+        assert!(validate(&clip(1000, 4000), &export(15, 480)).is_ok());
+        assert!(validate(&clip(0, 1), &export(MAX_FPS, MIN_WIDTH)).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_frame_rates_out_of_range() {
+        let expected = Err(format!("frame rate must be between 1 and {}", MAX_FPS));
+
+        assert_eq!(validate(&clip(0, 1000), &export(0, 480)), expected);
+        assert_eq!(
+            validate(&clip(0, 1000), &export(MAX_FPS + 1, 480)),
+            expected
+        );
+    }
+
+    #[test]
+    fn validate_rejects_narrow_output() {
+        assert_eq!(
+            validate(&clip(0, 1000), &export(15, MIN_WIDTH - 1)),
+            Err(format!("width must be at least {} pixels", MIN_WIDTH))
+        );
+    }
+
+    #[test]
+    fn validate_rejects_empty_ranges() {
+        let expected = Err("animation end must be after its start".to_string());
+
+        assert_eq!(validate(&clip(2000, 2000), &export(15, 480)), expected);
+        assert_eq!(validate(&clip(3000, 2000), &export(15, 480)), expected);
+
+        let packets = AnimationSource::Packets(RingBuffer::new(1000).stream());
+        assert_eq!(
+            validate(&packets, &export(15, 480)),
+            Err("no packets to export".to_string())
+        );
+    }
+
+    #[test]
+    fn gif_speed_runs_from_fastest_to_slowest() {
+        assert_eq!(gif_speed(1), 30);
+        assert_eq!(gif_speed(100), 1);
+        assert_eq!(gif_speed(50), 16);
+        // Out-of-range quality is clamped.
+        assert_eq!(gif_speed(0), 30);
+        assert_eq!(gif_speed(255), 1);
+
+        let speeds: Vec<i32> = (1..=100).map(gif_speed).collect();
+        assert!(speeds.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+}
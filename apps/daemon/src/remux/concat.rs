@@ -0,0 +1,464 @@
+use std::path::{Path, PathBuf};
+
+use gst::prelude::*;
+use gstreamer as gst;
+
+use super::{
+    file_size, link_to_mux, pad_kind, probe_streams, read_sidecar, reencode_video, wait_for_eos,
+    write_sidecar_if_needed, ClipFormat, ClipSidecar, ClipStreams, RemuxResult,
+};
+use crate::{
+    audio::{encoder::AudioEncoder, source::AudioSourceOutput},
+    gst_utils, logger,
+    video::encoder::VideoEncoder,
+};
+
+// Caps fields that must match for clips to be joined without re-encoding.
+// Stream format doesn't matter since h264parse rewrites it. codec_data is
+// only compared for MKV, see `video_copy_fields`.
+const VIDEO_COPY_FIELDS: &[&str] = &["profile", "level", "width", "height", "framerate"];
+const AUDIO_COPY_FIELDS: &[&str] = &["mpegversion", "rate", "channels"];
+
+/// Joins saved clips, in order, into one MP4 or MKV at `output_path`.
+/// Streams are copied when every clip has matching caps; otherwise all clips
+/// are decoded, scaled to the first clip's size and rate, and re-encoded with
+/// `encoder`. Audio is dropped unless every clip has an audio track. The
+/// first clip's metadata is carried over as tags and in the sidecar.
+pub fn concat_clips(
+    inputs: &[PathBuf],
+    output_path: &Path,
+    encoder: &VideoEncoder,
+) -> Result<RemuxResult, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    let format = match ClipFormat::from_path(output_path) {
+        Some(ClipFormat::Ts) | None => {
+            return Err("compilations are written as MP4 or MKV".to_string())
+        }
+        Some(format) => format,
+    };
+
+    let clips = inputs
+        .iter()
+        .map(|path| probe_streams(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let Some(first) = clips.first() else {
+        return Err("no clips to join".to_string());
+    };
+
+    if clips.iter().any(|clip| clip.video.is_none()) {
+        return Err("every clip needs a video track".to_string());
+    }
+
+    let with_audio = clips.iter().all(|clip| clip.audio.is_some());
+
+    let reencode = !all_match(&clips, |clip| &clip.video, &video_copy_fields(format))
+        || (with_audio && !all_match(&clips, |clip| &clip.audio, AUDIO_COPY_FIELDS));
+
+    if reencode {
+        logger::info("remux", "clip caps differ, re-encoding compilation");
+    }
+
+    let pipeline = gst::Pipeline::new();
+
+    // --- elements ---
+
+    let video_concat = gst_utils::make("concat")?;
+    let audio_concat = gst_utils::make("concat")?;
+    let mux = format.make_muxer()?;
+    let filesink = gst_utils::make("filesink")?;
+
+    let location = output_path
+        .to_str()
+        .ok_or("output path is not valid UTF-8")?;
+    filesink.set_property("location", location);
+
+    let metadata = read_sidecar(&inputs[0]).and_then(|sidecar| sidecar.metadata);
+    if let Some(metadata) = &metadata {
+        metadata.apply_tags(&mux);
+    }
+
+    pipeline
+        .add_many([&video_concat, &mux, &filesink])
+        .map_err(gst_utils::err)?;
+    mux.link(&filesink).map_err(gst_utils::err)?;
+
+    // --- shared tails into the muxer ---
+
+    let video_out = if reencode {
+        let encoded = reencode_video(&pipeline, encoder, &video_concat)?;
+        let h264parse = gst_utils::make("h264parse")?;
+        pipeline.add(&h264parse).map_err(gst_utils::err)?;
+        encoded.link(&h264parse).map_err(gst_utils::err)?;
+        h264parse
+    } else {
+        copied_video_tail(&pipeline, &video_concat, format)?
+    };
+
+    link_to_mux(&mux, format, "video", &video_out)?;
+
+    if with_audio {
+        pipeline.add(&audio_concat).map_err(gst_utils::err)?;
+
+        let audio_input = if reencode {
+            AudioEncoder
+                .build(
+                    &pipeline,
+                    AudioSourceOutput {
+                        element: audio_concat.clone(),
+                        volume: None,
+                    },
+                )
+                .map_err(gst_utils::err)?
+                .element
+        } else {
+            audio_concat.clone()
+        };
+
+        // Also turns the encoder's ADTS into raw AAC for the muxer.
+        let aacparse = gst_utils::make("aacparse")?;
+        pipeline.add(&aacparse).map_err(gst_utils::err)?;
+        audio_input.link(&aacparse).map_err(gst_utils::err)?;
+
+        link_to_mux(&mux, format, "audio", &aacparse)?;
+    }
+
+    // --- one source branch per clip, in concat pad order ---
+
+    for (path, clip) in inputs.iter().zip(&clips) {
+        let video_entry = if reencode {
+            build_conform_branch(&pipeline, "video", first.video.as_ref())?
+        } else {
+            build_queue(&pipeline)?
+        };
+        link_to_concat(&video_entry.1, &video_concat)?;
+
+        let audio_entry = if with_audio {
+            let entry = if reencode {
+                build_conform_branch(&pipeline, "audio", first.audio.as_ref())?
+            } else {
+                build_queue(&pipeline)?
+            };
+            link_to_concat(&entry.1, &audio_concat)?;
+            Some(entry.0)
+        } else if clip.audio.is_some() {
+            // Unused audio still has to be consumed so the demuxer keeps going.
+            let fakesink = gst_utils::make("fakesink")?;
+            fakesink.set_property("sync", false);
+            pipeline.add(&fakesink).map_err(gst_utils::err)?;
+            Some(fakesink)
+        } else {
+            None
+        };
+
+        add_clip_source(&pipeline, path, reencode, video_entry.0, audio_entry)?;
+    }
+
+    // --- run ---
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    wait_for_eos(&pipeline)?;
+
+    write_sidecar_if_needed(
+        output_path,
+        ClipSidecar {
+            markers: Vec::new(),
+            metadata,
+        },
+    )?;
+
+    Ok(RemuxResult {
+        duration_ms: clips.iter().filter_map(|clip| clip.duration_ms).sum(),
+        bytes_written: file_size(output_path)?,
+        pushed_bytes: 0,
+    })
+}
+
+fn all_match(
+    clips: &[ClipStreams],
+    caps: impl Fn(&ClipStreams) -> &Option<gst::Caps>,
+    fields: &[&str],
+) -> bool {
+    let mut keys = clips
+        .iter()
+        .map(|clip| caps_key(caps(clip).as_ref(), fields));
+    let first = keys.next().flatten();
+
+    keys.all(|key| key == first)
+}
+
+// Matroska can't switch codec_data mid-stream, MP4 gets it in-band as avc3
+// from `copied_video_tail`.
+fn video_copy_fields(format: ClipFormat) -> Vec<&'static str> {
+    let mut fields = VIDEO_COPY_FIELDS.to_vec();
+    if format == ClipFormat::Mkv {
+        fields.push("codec_data");
+    }
+    fields
+}
+
+// The caps structure reduced to `fields`, for comparing streams.
+fn caps_key(caps: Option<&gst::Caps>, fields: &[&str]) -> Option<gst::Structure> {
+    let structure = caps?.structure(0)?;
+    let mut key = gst::Structure::new_empty(structure.name());
+
+    for field in fields {
+        if let Ok(value) = structure.value(*field) {
+            key.set_value(*field, value.clone());
+        }
+    }
+
+    Some(key)
+}
+
+// concat -> h264parse (-> avc3 for MP4, since each clip brings its own SPS/PPS).
+fn copied_video_tail(
+    pipeline: &gst::Pipeline,
+    concat: &gst::Element,
+    format: ClipFormat,
+) -> Result<gst::Element, String> {
+    let h264parse = gst_utils::make("h264parse")?;
+    pipeline.add(&h264parse).map_err(gst_utils::err)?;
+    concat.link(&h264parse).map_err(gst_utils::err)?;
+
+    if format == ClipFormat::Mkv {
+        return Ok(h264parse);
+    }
+
+    let capsfilter = gst_utils::make("capsfilter")?;
+    let caps = gst::Caps::builder("video/x-h264")
+        .field("stream-format", "avc3")
+        .field("alignment", "au")
+        .build();
+    capsfilter.set_property("caps", &caps);
+
+    pipeline.add(&capsfilter).map_err(gst_utils::err)?;
+    h264parse.link(&capsfilter).map_err(gst_utils::err)?;
+
+    Ok(capsfilter)
+}
+
+// Returns (entry, exit) of a plain queue.
+fn build_queue(pipeline: &gst::Pipeline) -> Result<(gst::Element, gst::Element), String> {
+    let queue = gst_utils::make("queue")?;
+    pipeline.add(&queue).map_err(gst_utils::err)?;
+    Ok((queue.clone(), queue))
+}
+
+// Decoded frames -> queue -> convert/scale/rate -> capsfilter matching `target`.
+// Returns (entry, exit).
+fn build_conform_branch(
+    pipeline: &gst::Pipeline,
+    kind: &str,
+    target: Option<&gst::Caps>,
+) -> Result<(gst::Element, gst::Element), String> {
+    let (names, raw, fields): (&[&str], &str, &[&str]) = match kind {
+        "video" => (
+            &["queue", "videoconvert", "videoscale", "videorate"],
+            "video/x-raw",
+            &["width", "height", "framerate"],
+        ),
+        _ => (
+            &["queue", "audioconvert", "audioresample"],
+            "audio/x-raw",
+            &["rate", "channels"],
+        ),
+    };
+
+    let mut elements = names
+        .iter()
+        .map(|name| gst_utils::make(name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let capsfilter = gst_utils::make("capsfilter")?;
+    if let Some(mut key) = caps_key(target, fields) {
+        key.set_name(raw);
+        capsfilter.set_property("caps", gst::Caps::builder_full().structure(key).build());
+    }
+    elements.push(capsfilter);
+
+    pipeline.add_many(&elements).map_err(gst_utils::err)?;
+    gst::Element::link_many(&elements).map_err(gst_utils::err)?;
+
+    Ok((elements[0].clone(), elements[elements.len() - 1].clone()))
+}
+
+fn link_to_concat(element: &gst::Element, concat: &gst::Element) -> Result<(), String> {
+    let sink = concat
+        .request_pad_simple("sink_%u")
+        .ok_or("failed to request concat pad")?;
+
+    element
+        .static_pad("src")
+        .ok_or("missing concat input src pad")?
+        .link(&sink)
+        .map_err(gst_utils::err)?;
+
+    Ok(())
+}
+
+// filesrc -> parsebin (or decodebin when re-encoding), linking the first
+// video and audio streams to the given entries.
+fn add_clip_source(
+    pipeline: &gst::Pipeline,
+    path: &Path,
+    decode: bool,
+    video_entry: gst::Element,
+    audio_entry: Option<gst::Element>,
+) -> Result<(), String> {
+    let filesrc = gst_utils::make("filesrc")?;
+    let demux = gst_utils::make(if decode { "decodebin" } else { "parsebin" })?;
+
+    let location = path.to_str().ok_or("clip path is not valid UTF-8")?;
+    filesrc.set_property("location", location);
+
+    pipeline
+        .add_many([&filesrc, &demux])
+        .map_err(gst_utils::err)?;
+    filesrc.link(&demux).map_err(gst_utils::err)?;
+
+    demux.connect_pad_added(move |_, src_pad| {
+        let entry = match pad_kind(src_pad) {
+            Some("video") => &video_entry,
+            Some("audio") => match audio_entry.as_ref() {
+                Some(entry) => entry,
+                None => return,
+            },
+            _ => return,
+        };
+
+        if let Some(sink) = entry.static_pad("sink") {
+            if !sink.is_linked() {
+                let _ = src_pad.link(&sink);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video_caps(level: &str, width: i32, codec_data: &[u8]) -> gst::Caps {
+        gst::Caps::builder("video/x-h264")
+            .field("stream-format", "avc")
+            .field("alignment", "au")
+            .field("profile", "high")
+            .field("level", level)
+            .field("width", width)
+            .field("height", 1080i32)
+            .field("framerate", gst::Fraction::new(60, 1))
+            .field("codec_data", gst::Buffer::from_slice(codec_data.to_vec()))
+            .build()
+    }
+
+    fn audio_caps(rate: i32) -> gst::Caps {
+        gst::Caps::builder("audio/mpeg")
+            .field("mpegversion", 4i32)
+            .field("stream-format", "raw")
+            .field("rate", rate)
+            .field("channels", 2i32)
+            .build()
+    }
+
+    fn clip(video: gst::Caps, audio: Option<gst::Caps>) -> ClipStreams {
+        ClipStreams {
+            video: Some(video),
+            audio,
+            duration_ms: None,
+        }
+    }
+
+    #[test]
+    fn caps_key_keeps_only_the_compared_fields() {
+        gst::init().unwrap();
+
+        let key = caps_key(Some(&video_caps("4.2", 1920, &[1, 2])), VIDEO_COPY_FIELDS).unwrap();
+
+        assert_eq!(key.name(), "video/x-h264");
+        assert_eq!(key.n_fields() as usize, VIDEO_COPY_FIELDS.len());
+        assert_eq!(key.get::<&str>("level").unwrap(), "4.2");
+        assert!(!key.has_field("codec_data"));
+        assert!(!key.has_field("stream-format"));
+
+        // Missing fields are left out rather than failing.
+        let sparse = gst::Caps::builder("video/x-h264")
+            .field("width", 1280i32)
+            .build();
+        let key = caps_key(Some(&sparse), VIDEO_COPY_FIELDS).unwrap();
+        assert_eq!(key.n_fields(), 1);
+
+        assert!(caps_key(None, VIDEO_COPY_FIELDS).is_none());
+    }
+
+    #[test]
+    fn all_match_ignores_codec_data_and_stream_format() {
+        gst::init().unwrap();
+
+        let mut other = video_caps("4.2", 1920, &[3, 4, 5]);
+        other.get_mut().unwrap().set("stream-format", "byte-stream");
+        let clips = [
+            clip(video_caps("4.2", 1920, &[1, 2]), Some(audio_caps(48_000))),
+            clip(other, Some(audio_caps(48_000))),
+        ];
+
+        assert!(all_match(&clips, |clip| &clip.video, VIDEO_COPY_FIELDS));
+        assert!(all_match(&clips, |clip| &clip.audio, AUDIO_COPY_FIELDS));
+    }
+
+    #[test]
+    fn mkv_copies_only_clips_with_the_same_codec_data() {
+        gst::init().unwrap();
+
+        let clips = [
+            clip(video_caps("4.2", 1920, &[1, 2]), None),
+            clip(video_caps("4.2", 1920, &[3, 4, 5]), None),
+        ];
+
+        assert!(all_match(
+            &clips,
+            |clip| &clip.video,
+            &video_copy_fields(ClipFormat::Mp4)
+        ));
+        assert!(!all_match(
+            &clips,
+            |clip| &clip.video,
+            &video_copy_fields(ClipFormat::Mkv)
+        ));
+    }
+
+    #[test]
+    fn all_match_detects_differing_streams() {
+        gst::init().unwrap();
+
+        let level = [
+            clip(video_caps("4.2", 1920, &[1]), None),
+            clip(video_caps("5.1", 1920, &[1]), None),
+        ];
+        assert!(!all_match(&level, |clip| &clip.video, VIDEO_COPY_FIELDS));
+
+        let size = [
+            clip(video_caps("4.2", 1920, &[1]), None),
+            clip(video_caps("4.2", 1280, &[1]), None),
+        ];
+        assert!(!all_match(&size, |clip| &clip.video, VIDEO_COPY_FIELDS));
+
+        let rate = [
+            clip(video_caps("4.2", 1920, &[1]), Some(audio_caps(48_000))),
+            clip(video_caps("4.2", 1920, &[1]), Some(audio_caps(44_100))),
+        ];
+        assert!(!all_match(&rate, |clip| &clip.audio, AUDIO_COPY_FIELDS));
+
+        let missing = [
+            clip(video_caps("4.2", 1920, &[1]), Some(audio_caps(48_000))),
+            clip(video_caps("4.2", 1920, &[1]), None),
+        ];
+        assert!(!all_match(&missing, |clip| &clip.audio, AUDIO_COPY_FIELDS));
+    }
+}
@@ -0,0 +1,388 @@
+use std::{fs, path::Path};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::Deserialize;
+
+use super::{
+    link_to_mux, pad_kind, probe_streams, read_sidecar, reencode_video, watch_pipeline,
+    write_sidecar_if_needed, CancelToken, ClipFormat, ClipMetadata, RemuxResult,
+};
+use crate::{
+    audio::{encoder::AudioEncoder, source::AudioSourceOutput},
+    encoders, gst_utils, logger,
+    video::encoder::VideoEncoder,
+};
+
+// AudioEncoder always encodes at this rate.
+const AUDIO_KBPS: u64 = 192;
+// Headroom for container overhead and encoder rate-control overshoot.
+const SIZE_MARGIN: f64 = 0.9;
+// Below this the picture falls apart; better to tell the user to downscale or trim.
+const MIN_VIDEO_KBPS: u64 = 150;
+const FALLBACK_FRAMERATE: u32 = 60;
+// Encodes tried before an oversized export is given up on.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Settings for `export_to_size`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SizeExport {
+    /// Upper bound for the output file, e.g. 8 MB for chat uploads.
+    pub target_bytes: u64,
+    /// Downscales to this height, keeping the aspect ratio. Never upscales.
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// One of the ids returned by `list_video_encoders`.
+    pub encoder_id: String,
+}
+
+/// Decodes `input_path` and re-encodes it into `output_path`, with the video
+/// bitrate computed from the clip duration so the file lands under
+/// `target_bytes`. An encode that overshoots is retried at a bitrate scaled
+/// down by the overshoot; progress restarts from 0.0 for each attempt. The
+/// output is removed on failure, cancellation, or when it stays too large.
+/// The clip's markers and metadata are carried over.
+pub fn export_to_size(
+    input_path: &Path,
+    output_path: &Path,
+    export: &SizeExport,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(f32),
+) -> Result<RemuxResult, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    if encoders::find_video_encoder(&export.encoder_id)
+        .map_err(gst_utils::err)?
+        .is_none()
+    {
+        return Err(format!("encoder not available: {}", export.encoder_id));
+    }
+
+    let format = ClipFormat::from_path(output_path).ok_or("unsupported output extension")?;
+    let streams = probe_streams(input_path)?;
+    let video_caps = streams.video.as_ref().ok_or("clip has no video track")?;
+    let duration_ms = streams
+        .duration_ms
+        .filter(|ms| *ms > 0)
+        .ok_or("clip duration is unknown")?;
+
+    let audio_kbps = if streams.audio.is_some() {
+        AUDIO_KBPS
+    } else {
+        0
+    };
+    let mut video_kbps =
+        video_kbps_for(export.target_bytes, duration_ms, audio_kbps).ok_or_else(|| {
+            format!(
+                "{} MB is too small for a {}s clip",
+                export.target_bytes / 1_000_000,
+                duration_ms / 1000
+            )
+        })?;
+
+    let structure = video_caps.structure(0).ok_or("clip has no video caps")?;
+    let framerate = structure
+        .get::<gst::Fraction>("framerate")
+        .ok()
+        .filter(|rate| rate.numer() > 0 && rate.denom() > 0)
+        .map(|rate| (rate.numer() as f64 / rate.denom() as f64).round() as u32)
+        .unwrap_or(FALLBACK_FRAMERATE);
+    let source_height = structure.get::<i32>("height").ok();
+
+    let target_height = match (export.max_height, source_height) {
+        (Some(max), Some(source)) if (max as i32) < source => Some(max as i32),
+        (Some(max), None) => Some(max as i32),
+        _ => None,
+    };
+
+    let sidecar = read_sidecar(input_path);
+    let settings = EncodeSettings {
+        format,
+        encoder_id: &export.encoder_id,
+        framerate,
+        target_height,
+        with_audio: streams.audio.is_some(),
+        metadata: sidecar
+            .as_ref()
+            .and_then(|sidecar| sidecar.metadata.as_ref()),
+    };
+
+    let mut attempt = 1;
+
+    loop {
+        if let Err(err) = encode(
+            input_path,
+            output_path,
+            &settings,
+            video_kbps,
+            cancel,
+            &mut on_progress,
+        ) {
+            let _ = fs::remove_file(output_path);
+            return Err(err);
+        }
+
+        let bytes_written = match fs::metadata(output_path) {
+            Ok(metadata) => metadata.len(),
+            Err(err) => {
+                let _ = fs::remove_file(output_path);
+                return Err(err.to_string());
+            }
+        };
+
+        if bytes_written <= export.target_bytes {
+            if let Some(sidecar) = sidecar {
+                write_sidecar_if_needed(output_path, sidecar)?;
+            }
+
+            on_progress(1.0);
+            return Ok(RemuxResult {
+                duration_ms,
+                bytes_written,
+                pushed_bytes: 0,
+            });
+        }
+
+        let retry = retry_video_kbps(video_kbps, audio_kbps, export.target_bytes, bytes_written)
+            .filter(|_| attempt < MAX_ATTEMPTS);
+
+        let Some(retry) = retry else {
+            let _ = fs::remove_file(output_path);
+            return Err(format!(
+                "export came out at {:.1} MB, over the {} MB target",
+                bytes_written as f64 / 1_000_000.0,
+                export.target_bytes / 1_000_000
+            ));
+        };
+
+        logger::warn(
+            "remux",
+            format!(
+                "export is {} bytes, over the {} byte target; retrying at {} kbps",
+                bytes_written, export.target_bytes, retry
+            ),
+        );
+        video_kbps = retry;
+        attempt += 1;
+    }
+}
+
+// Video bitrate that fits `duration_ms` of video plus `audio_kbps` of audio
+// into `target_bytes` with `SIZE_MARGIN` to spare, or None when that is below
+// `MIN_VIDEO_KBPS`.
+fn video_kbps_for(target_bytes: u64, duration_ms: u64, audio_kbps: u64) -> Option<u64> {
+    let total_kbps = (target_bytes as f64 * 8.0 * SIZE_MARGIN) / duration_ms.max(1) as f64;
+    let video_kbps = (total_kbps as u64).saturating_sub(audio_kbps);
+
+    (video_kbps >= MIN_VIDEO_KBPS).then_some(video_kbps)
+}
+
+// Next video bitrate after an encode at `video_kbps` came out at
+// `actual_bytes`: scaled by how far the whole file overshot, again with
+// `SIZE_MARGIN`. Audio doesn't shrink, so its share comes off the video. None
+// when that is below `MIN_VIDEO_KBPS` or no lower than before.
+fn retry_video_kbps(
+    video_kbps: u64,
+    audio_kbps: u64,
+    target_bytes: u64,
+    actual_bytes: u64,
+) -> Option<u64> {
+    let total_kbps = (video_kbps + audio_kbps) as f64;
+    let scaled_kbps = total_kbps * SIZE_MARGIN * target_bytes as f64 / actual_bytes.max(1) as f64;
+    let retry = (scaled_kbps as u64).saturating_sub(audio_kbps);
+
+    (retry >= MIN_VIDEO_KBPS && retry < video_kbps).then_some(retry)
+}
+
+// What stays the same between the attempts of one export.
+struct EncodeSettings<'a> {
+    format: ClipFormat,
+    encoder_id: &'a str,
+    framerate: u32,
+    target_height: Option<i32>,
+    with_audio: bool,
+    metadata: Option<&'a ClipMetadata>,
+}
+
+// One decode/re-encode pass at `video_kbps`. Blocks until EOS.
+fn encode(
+    input_path: &Path,
+    output_path: &Path,
+    settings: &EncodeSettings,
+    video_kbps: u64,
+    cancel: &CancelToken,
+    on_progress: &mut dyn FnMut(f32),
+) -> Result<(), String> {
+    let format = settings.format;
+    let encoder = VideoEncoder::new(settings.encoder_id, settings.framerate, video_kbps as u32);
+
+    let pipeline = gst::Pipeline::new();
+
+    // --- elements ---
+
+    let filesrc = gst_utils::make("filesrc")?;
+    let decodebin = gst_utils::make("decodebin")?;
+    let video_queue = gst_utils::make("queue")?;
+    let video_convert = gst_utils::make("videoconvert")?;
+    let videoscale = gst_utils::make("videoscale")?;
+    let scale_caps = gst_utils::make("capsfilter")?;
+    let h264parse = gst_utils::make("h264parse")?;
+    let mux = format.make_muxer()?;
+    let filesink = gst_utils::make("filesink")?;
+
+    // --- config ---
+
+    let input = input_path.to_str().ok_or("input path is not valid UTF-8")?;
+    filesrc.set_property("location", input);
+
+    let location = output_path
+        .to_str()
+        .ok_or("output path is not valid UTF-8")?;
+    filesink.set_property("location", location);
+
+    if let Some(metadata) = settings.metadata {
+        metadata.apply_tags(&mux);
+    }
+
+    if let Some(height) = settings.target_height {
+        // Even heights keep 4:2:0 encoders happy.
+        let caps = gst::Caps::builder("video/x-raw")
+            .field("height", height & !1)
+            .build();
+        scale_caps.set_property("caps", &caps);
+    }
+
+    // --- pipeline assembly ---
+
+    pipeline
+        .add_many([
+            &filesrc,
+            &decodebin,
+            &video_queue,
+            &video_convert,
+            &videoscale,
+            &scale_caps,
+            &h264parse,
+            &mux,
+            &filesink,
+        ])
+        .map_err(gst_utils::err)?;
+
+    filesrc.link(&decodebin).map_err(gst_utils::err)?;
+    gst::Element::link_many([&video_queue, &video_convert, &videoscale, &scale_caps])
+        .map_err(gst_utils::err)?;
+
+    let encoded = reencode_video(&pipeline, &encoder, &scale_caps)?;
+    encoded.link(&h264parse).map_err(gst_utils::err)?;
+    mux.link(&filesink).map_err(gst_utils::err)?;
+    link_to_mux(&mux, format, "video", &h264parse)?;
+
+    let audio_queue = if settings.with_audio {
+        let queue = gst_utils::make("queue")?;
+        let convert = gst_utils::make("audioconvert")?;
+        let resample = gst_utils::make("audioresample")?;
+        let aacparse = gst_utils::make("aacparse")?;
+
+        pipeline
+            .add_many([&queue, &convert, &resample])
+            .map_err(gst_utils::err)?;
+        gst::Element::link_many([&queue, &convert, &resample]).map_err(gst_utils::err)?;
+
+        let encoded_audio = AudioEncoder
+            .build(
+                &pipeline,
+                AudioSourceOutput {
+                    element: resample,
+                    volume: None,
+                },
+            )
+            .map_err(gst_utils::err)?;
+
+        // Turns the encoder's ADTS into raw AAC for the muxer.
+        pipeline.add(&aacparse).map_err(gst_utils::err)?;
+        encoded_audio
+            .element
+            .link(&aacparse)
+            .map_err(gst_utils::err)?;
+        link_to_mux(&mux, format, "audio", &aacparse)?;
+
+        Some(queue)
+    } else {
+        None
+    };
+
+    // --- dynamic pad handling ---
+
+    decodebin.connect_pad_added(move |_, src_pad| {
+        let entry = match pad_kind(src_pad) {
+            Some("video") => &video_queue,
+            Some("audio") => match audio_queue.as_ref() {
+                Some(queue) => queue,
+                None => return,
+            },
+            _ => return,
+        };
+
+        if let Some(sink) = entry.static_pad("sink") {
+            if !sink.is_linked() {
+                let _ = src_pad.link(&sink);
+            }
+        }
+    });
+
+    // --- run ---
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    on_progress(0.0);
+
+    watch_pipeline(&pipeline, Some(cancel), on_progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1_000_000;
+
+    #[test]
+    fn video_bitrate_fills_the_target_with_margin() {
+        // 8 MB over 30 s: 8e6 * 8 * 0.9 / 30_000 = 1920 kbps in total.
+        assert_eq!(video_kbps_for(8 * MB, 30_000, 0), Some(1920));
+        assert_eq!(
+            video_kbps_for(8 * MB, 30_000, AUDIO_KBPS),
+            Some(1920 - AUDIO_KBPS)
+        );
+
+        let kbps = video_kbps_for(25 * MB, 95_000, AUDIO_KBPS).unwrap();
+        let estimated_bytes = (kbps + AUDIO_KBPS) * 95_000 / 8;
+        assert!(estimated_bytes <= 25 * MB);
+    }
+
+    #[test]
+    fn video_bitrate_below_the_minimum_is_refused() {
+        // 1 MB over 60 s leaves ~120 kbps in total.
+        assert_eq!(video_kbps_for(MB, 60_000, 0), None);
+        // Audio alone would eat the budget.
+        assert_eq!(video_kbps_for(2 * MB, 60_000, AUDIO_KBPS), None);
+    }
+
+    #[test]
+    fn retry_scales_down_by_the_overshoot() {
+        // 20% over the target with 1728 + 192 kbps.
+        let retry = retry_video_kbps(1728, AUDIO_KBPS, 8 * MB, 9_600_000).unwrap();
+
+        assert_eq!(retry, 1440 - AUDIO_KBPS);
+        assert!(retry < 1728);
+    }
+
+    #[test]
+    fn retry_gives_up_below_the_minimum_or_without_progress() {
+        assert_eq!(retry_video_kbps(200, 0, 8 * MB, 16 * MB), None);
+        assert_eq!(retry_video_kbps(1000, AUDIO_KBPS, 8 * MB, 40 * MB), None);
+        // Only barely over: 0.9 * (target / actual) still lowers it.
+        assert!(retry_video_kbps(1000, 0, 8 * MB, 8 * MB + 1).is_some_and(|kbps| kbps < 1000));
+    }
+}
@@ -0,0 +1,1184 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    encoders, gst_utils, logger,
+    ring_buffer::{Marker, Packet},
+    video::{encoder::VideoEncoder, graph::GraphOutput},
+};
+
+mod animation;
+mod concat;
+mod export;
+mod probe;
+mod thumbnail;
+mod timeline;
+mod trim;
+mod verify;
+
+pub use animation::{export_animation, AnimationExport, AnimationFormat, AnimationSource};
+pub use concat::concat_clips;
+pub use export::{export_to_size, SizeExport};
+pub use probe::{probe_streams, ClipStreams};
+pub use thumbnail::{
+    find_thumbnail, generate_thumbnails, is_thumbnail, thumbnail_path, PosterFrame,
+    ThumbnailFormat, ThumbnailOptions, Thumbnails,
+};
+pub use trim::{trim_clip, TrimMode};
+pub use verify::{verify_clip, ClipReport};
+
+/// Container written for saved clips.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipFormat {
+    #[default]
+    Mp4,
+    /// Survives partial writes and carries markers as real chapters.
+    Mkv,
+    /// moov up front plus moof fragments, so partial files play and the
+    /// output can be served progressively.
+    FragmentedMp4,
+    /// The raw MPEG-TS exactly as mpegtsmux produced it, with no remux step.
+    Ts,
+}
+
+// One fragment per second keeps partial writes cheap without bloating the index.
+const FRAGMENT_DURATION_MS: u32 = 1000;
+
+impl ClipFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ClipFormat::Mp4 | ClipFormat::FragmentedMp4 => "mp4",
+            ClipFormat::Mkv => "mkv",
+            ClipFormat::Ts => "ts",
+        }
+    }
+
+    /// Best guess from a saved clip's extension. Fragmented MP4 reads as plain MP4.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "mp4" => Some(ClipFormat::Mp4),
+            "mkv" => Some(ClipFormat::Mkv),
+            "ts" => Some(ClipFormat::Ts),
+            _ => None,
+        }
+    }
+
+    // Request pad template on the muxer for a "video" or "audio" stream.
+    fn pad_template(&self, kind: &str) -> &'static str {
+        match (self, kind) {
+            (ClipFormat::Ts, _) => "sink_%d",
+            (_, "video") => "video_%u",
+            _ => "audio_%u",
+        }
+    }
+
+    fn muxer_factory(&self) -> &'static str {
+        match self {
+            ClipFormat::Mp4 | ClipFormat::FragmentedMp4 => "mp4mux",
+            ClipFormat::Mkv => "matroskamux",
+            // Only used when re-muxing existing clips; ring buffer dumps skip the muxer.
+            ClipFormat::Ts => "mpegtsmux",
+        }
+    }
+
+    fn make_muxer(&self) -> Result<gst::Element, String> {
+        let mux = gst_utils::make(self.muxer_factory())?;
+
+        match self {
+            ClipFormat::Mp4 => mux.set_property("faststart", true),
+            ClipFormat::FragmentedMp4 => {
+                mux.set_property("fragment-duration", FRAGMENT_DURATION_MS);
+                mux.set_property("streamable", true);
+            }
+            ClipFormat::Mkv | ClipFormat::Ts => {}
+        }
+
+        Ok(mux)
+    }
+
+    fn supports_chapters(&self) -> bool {
+        matches!(self, ClipFormat::Mkv)
+    }
+}
+
+// How long a pipeline may go without bus messages or position changes.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+// Queued TS bytes before appsrc signals enough-data, a few seconds of video.
+const APPSRC_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Error returned by jobs stopped through their `CancelToken`.
+pub const CANCELLED: &str = "cancelled";
+
+/// Stops a running export from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RemuxResult {
+    pub duration_ms: u64,
+    /// Size of the finished file on disk.
+    pub bytes_written: u64,
+    /// Packet payload bytes handed to the writer or muxer; 0 for outputs
+    /// that weren't built from buffered packets.
+    pub pushed_bytes: u64,
+}
+
+/// JSON written next to a saved clip, e.g. `clip-….mp4` -> `clip-….json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClipSidecar {
+    #[serde(default)]
+    pub markers: Vec<ClipMarker>,
+    #[serde(default)]
+    pub metadata: Option<ClipMetadata>,
+}
+
+/// Describes where a clip came from. Written as container tags and to the sidecar.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClipMetadata {
+    /// RFC 3339 local time of the save.
+    pub created_at: String,
+    #[serde(default)]
+    pub device_label: Option<String>,
+    pub encoder_id: String,
+    pub bitrate_kbps: u32,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl ClipMetadata {
+    // Sets the tags on `mux` when the muxer takes them.
+    fn apply_tags(&self, mux: &gst::Element) {
+        if let Some(setter) = mux.dynamic_cast_ref::<gst::TagSetter>() {
+            setter.merge_tags(&self.tags(), gst::TagMergeMode::Replace);
+        }
+    }
+
+    fn tags(&self) -> gst::TagList {
+        let mut tags = gst::TagList::new();
+
+        {
+            let tags = tags.get_mut().unwrap();
+
+            if let Ok(created_at) = gst::DateTime::from_iso8601_string(&self.created_at) {
+                tags.add::<gst::tags::DateTime>(&created_at, gst::TagMergeMode::Replace);
+            }
+            if let Some(device_label) = self.device_label.as_deref() {
+                tags.add::<gst::tags::DeviceModel>(&device_label, gst::TagMergeMode::Replace);
+            }
+            if let Some(title) = self.title.as_deref() {
+                tags.add::<gst::tags::Title>(&title, gst::TagMergeMode::Replace);
+            }
+
+            tags.add::<gst::tags::Encoder>(&self.encoder_id.as_str(), gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::Bitrate>(
+                &self.bitrate_kbps.saturating_mul(1000),
+                gst::TagMergeMode::Replace,
+            );
+        }
+
+        tags
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipMarker {
+    pub label: String,
+    /// Offset from the first packet of the clip.
+    pub offset_ms: u64,
+}
+
+pub fn sidecar_path(clip_path: &Path) -> PathBuf {
+    clip_path.with_extension("json")
+}
+
+pub fn write_sidecar(clip_path: &Path, sidecar: &ClipSidecar) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(sidecar).map_err(gst_utils::err)?;
+    fs::write(sidecar_path(clip_path), data).map_err(gst_utils::err)
+}
+
+pub fn read_sidecar(clip_path: &Path) -> Option<ClipSidecar> {
+    let data = fs::read_to_string(sidecar_path(clip_path)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+// Skips the sidecar when there is nothing to put in it.
+fn write_sidecar_if_needed(clip_path: &Path, sidecar: ClipSidecar) -> Result<(), String> {
+    if sidecar.markers.is_empty() && sidecar.metadata.is_none() {
+        return Ok(());
+    }
+
+    write_sidecar(clip_path, &sidecar)
+}
+
+fn clip_markers(first_dts_ns: u64, markers: &[Marker]) -> Vec<ClipMarker> {
+    markers
+        .iter()
+        .map(|marker| ClipMarker {
+            label: marker.label.clone(),
+            offset_ms: marker.dts_ns.saturating_sub(first_dts_ns) / 1_000_000,
+        })
+        .collect()
+}
+
+// Moves marker offsets past the jumps closed up while remuxing.
+fn map_markers(markers: &[ClipMarker], timeline: &timeline::TimelineReport) -> Vec<ClipMarker> {
+    markers
+        .iter()
+        .map(|marker| ClipMarker {
+            label: marker.label.clone(),
+            offset_ms: timeline.map_ms(marker.offset_ms),
+        })
+        .collect()
+}
+
+// Builds a TOC with one chapter per marker, for muxers that implement GstTocSetter.
+fn markers_toc(markers: &[ClipMarker]) -> gst::Toc {
+    let mut toc = gst::Toc::new(gst::TocScope::Global);
+    let mut edition = gst::TocEntry::new(gst::TocEntryType::Edition, "edition");
+
+    for (index, marker) in markers.iter().enumerate() {
+        let start_ns = (marker.offset_ms * 1_000_000) as i64;
+        let stop_ns = markers
+            .get(index + 1)
+            .map(|next| (next.offset_ms * 1_000_000) as i64)
+            .unwrap_or(-1);
+
+        let mut tags = gst::TagList::new();
+        tags.get_mut()
+            .unwrap()
+            .add::<gst::tags::Title>(&marker.label.as_str(), gst::TagMergeMode::Replace);
+
+        let mut chapter =
+            gst::TocEntry::new(gst::TocEntryType::Chapter, &format!("chapter-{}", index));
+        {
+            let chapter = chapter.get_mut().unwrap();
+            chapter.set_start_stop_times(start_ns, stop_ns);
+            chapter.set_tags(tags);
+        }
+
+        edition.get_mut().unwrap().append_sub_entry(chapter);
+    }
+
+    toc.get_mut().unwrap().append_entry(edition);
+    toc
+}
+
+/// Remuxes buffered TS packets into an MP4. mp4mux has no chapter support,
+/// so `markers` are only written to the JSON sidecar.
+pub fn remux_ts_to_mp4(
+    packets: &[Packet],
+    markers: &[Marker],
+    output_path: &Path,
+) -> Result<RemuxResult, String> {
+    remux_ts(packets, markers, None, ClipFormat::Mp4, output_path)
+}
+
+/// Remuxes buffered TS packets into the given container. Markers and metadata
+/// go to the JSON sidecar, and also into the container when it supports them.
+/// The output timeline starts at zero, with capture gaps and jumps repaired.
+pub fn remux_ts(
+    packets: &[Packet],
+    markers: &[Marker],
+    metadata: Option<&ClipMetadata>,
+    format: ClipFormat,
+    output_path: &Path,
+) -> Result<RemuxResult, String> {
+    RemuxJob {
+        markers,
+        metadata,
+        format,
+        cancel: None,
+    }
+    .run(packets, output_path, |_| {})
+}
+
+/// A clip save that reports progress and can be cancelled from another thread.
+pub struct RemuxJob<'a> {
+    pub markers: &'a [Marker],
+    pub metadata: Option<&'a ClipMetadata>,
+    pub format: ClipFormat,
+    pub cancel: Option<CancelToken>,
+}
+
+impl RemuxJob<'_> {
+    /// Blocks until the clip is written. `on_progress` receives 0.0..=1.0: the
+    /// average of packets pushed versus total and of muxed time versus clip
+    /// duration. A cancelled job removes its partial output and fails with
+    /// `CANCELLED`.
+    pub fn run(
+        &self,
+        packets: &[Packet],
+        output_path: &Path,
+        on_progress: impl FnMut(f32),
+    ) -> Result<RemuxResult, String> {
+        self.run_stream(packets.iter().cloned(), output_path, on_progress)
+    }
+
+    /// Like `run`, but pulls packets only as fast as the muxer takes them, so
+    /// the clip can come from a disk-backed buffer or a post-roll that is
+    /// still being captured without being held in memory. Progress only
+    /// moves before the end when the iterator knows an upper bound on its
+    /// length.
+    pub fn run_stream(
+        &self,
+        packets: impl IntoIterator<Item = Packet>,
+        output_path: &Path,
+        mut on_progress: impl FnMut(f32),
+    ) -> Result<RemuxResult, String> {
+        let mut packets = packets.into_iter().peekable();
+
+        let result = if self.format == ClipFormat::Ts {
+            self.dump(&mut packets, output_path, &mut on_progress)
+        } else {
+            self.remux(&mut packets, output_path, &mut on_progress)
+        };
+
+        if result.is_err() {
+            let _ = fs::remove_file(output_path);
+        }
+
+        result
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    fn remux(
+        &self,
+        packets: &mut Peekable<impl Iterator<Item = Packet>>,
+        output_path: &Path,
+        on_progress: &mut dyn FnMut(f32),
+    ) -> Result<RemuxResult, String> {
+        let RemuxJob {
+            markers,
+            metadata,
+            format,
+            ..
+        } = *self;
+
+        gst::init().map_err(gst_utils::err)?;
+
+        let mut stats = PushStats::new(packets).ok_or("no packets to remux")?;
+
+        let pipeline = gst::Pipeline::new();
+
+        // --- elements ---
+
+        let appsrc = make_ts_appsrc()?;
+
+        let tsdemux = gst_utils::make("tsdemux")?;
+        let h264parse = gst_utils::make("h264parse")?;
+        let aacparse = gst_utils::make("aacparse")?;
+        let mux = format.make_muxer()?;
+        let video_queue = gst_utils::make("queue")?;
+        let audio_queue = gst_utils::make("queue")?;
+        let filesink = gst_utils::make("filesink")?;
+
+        // --- config ---
+
+        let location = output_path
+            .to_str()
+            .ok_or("output path is not valid UTF-8")?;
+        filesink.set_property("location", location);
+
+        // --- pipeline assembly ---
+
+        pipeline
+            .add_many([
+                appsrc.upcast_ref(),
+                &tsdemux,
+                &video_queue,
+                &audio_queue,
+                &h264parse,
+                &aacparse,
+                &mux,
+                &filesink,
+            ])
+            .map_err(gst_utils::err)?;
+
+        appsrc.link(&tsdemux).map_err(gst_utils::err)?;
+        mux.link(&filesink).map_err(gst_utils::err)?;
+
+        // --- dynamic pad handling ---
+
+        let video_queue_sink = video_queue
+            .static_pad("sink")
+            .ok_or("missing video queue sink")?;
+        let audio_queue_sink = audio_queue
+            .static_pad("sink")
+            .ok_or("missing audio queue sink")?;
+
+        tsdemux.connect_pad_added(move |_, src_pad| {
+            let Some(caps) = src_pad.current_caps() else {
+                return;
+            };
+            let Some(s) = caps.structure(0) else { return };
+
+            let name = s.name();
+
+            if name.starts_with("video/") {
+                let _ = src_pad.link(&video_queue_sink);
+            } else if name.starts_with("audio/") {
+                let _ = src_pad.link(&audio_queue_sink);
+            }
+        });
+
+        video_queue.link(&h264parse).map_err(gst_utils::err)?;
+        audio_queue.link(&aacparse).map_err(gst_utils::err)?;
+
+        // --- muxer pads ---
+
+        let video_pad = mux
+            .request_pad_simple(format.pad_template("video"))
+            .ok_or("failed to request muxer video pad")?;
+        let audio_pad = mux
+            .request_pad_simple(format.pad_template("audio"))
+            .ok_or("failed to request muxer audio pad")?;
+
+        let video_src = h264parse
+            .static_pad("src")
+            .ok_or("missing h264parse on src pad")?;
+        let audio_src = aacparse
+            .static_pad("src")
+            .ok_or("missing aacparse on src pad")?;
+
+        video_src.link(&video_pad).map_err(gst_utils::err)?;
+        audio_src.link(&audio_pad).map_err(gst_utils::err)?;
+
+        // Clips start at zero whatever the capture timestamps were.
+        let timeline = timeline::normalize_timestamps(&[video_src.clone(), audio_src]);
+
+        let source_markers = clip_markers(stats.first_dts_ns, markers);
+
+        // Chapters go in once video has ended and every closed jump is known,
+        // so they land where the sidecar markers do. matroskamux writes a TOC
+        // set after the header when it finalizes the file.
+        if format.supports_chapters() && !source_markers.is_empty() {
+            if let Ok(setter) = mux.clone().dynamic_cast::<gst::TocSetter>() {
+                let markers = source_markers.clone();
+                let timeline = timeline.clone();
+
+                video_src.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+                    let Some(gst::PadProbeData::Event(ref event)) = info.data else {
+                        return gst::PadProbeReturn::Ok;
+                    };
+                    if event.type_() != gst::EventType::Eos {
+                        return gst::PadProbeReturn::Ok;
+                    }
+
+                    setter.set_toc(Some(&markers_toc(&map_markers(&markers, &timeline))));
+                    gst::PadProbeReturn::Remove
+                });
+            }
+        }
+
+        if let Some(metadata) = metadata {
+            metadata.apply_tags(&mux);
+        }
+
+        // --- start pipeline ---
+
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(gst_utils::err)?;
+
+        // --- push packets ---
+
+        // Muxer position queries are cheap, but not per packet.
+        let report_every = (stats.expected / 100).max(1);
+
+        let pushed = push_packets(
+            &appsrc,
+            &pipeline,
+            packets,
+            self.cancel.as_ref(),
+            &mut |packet| {
+                stats.record(packet);
+
+                if stats.count % report_every == 0 {
+                    let pushed = stats.pushed_fraction();
+                    let muxed = muxed_fraction(&mux, stats.span_ns()) * pushed;
+                    on_progress((pushed + muxed) / 2.0);
+                }
+            },
+        );
+
+        if let Err(err) = pushed {
+            pipeline.set_state(gst::State::Null).ok();
+            return Err(err);
+        }
+
+        // --- BLOCK until EOS ---
+
+        let span_ns = stats.span_ns();
+
+        watch_pipeline(&pipeline, self.cancel.as_ref(), &mut |_| {
+            on_progress((1.0 + muxed_fraction(&mux, span_ns)) / 2.0)
+        })?;
+
+        on_progress(1.0);
+
+        // Markers and duration follow the media, which moved where jumps
+        // were closed up.
+        let shift_ns = timeline.total_shift_ns();
+        if shift_ns != 0 {
+            logger::info(
+                "remux",
+                format!("clip timeline shifted by {} ms", shift_ns / 1_000_000),
+            );
+        }
+
+        write_sidecar_if_needed(
+            output_path,
+            ClipSidecar {
+                markers: map_markers(&source_markers, &timeline),
+                metadata: metadata.cloned(),
+            },
+        )?;
+
+        Ok(RemuxResult {
+            duration_ms: timeline.end_ns().unwrap_or(stats.span_ns()) / 1_000_000,
+            bytes_written: file_size(output_path)?,
+            pushed_bytes: stats.bytes,
+        })
+    }
+
+    // TS clips are the buffered bytes as-is. Callers should pass packets that
+    // start on a keyframe, e.g. from `RingBuffer::snapshot_from_keyframe`.
+    // Metadata only goes to the sidecar.
+    fn dump(
+        &self,
+        packets: &mut Peekable<impl Iterator<Item = Packet>>,
+        output_path: &Path,
+        on_progress: &mut dyn FnMut(f32),
+    ) -> Result<RemuxResult, String> {
+        let mut stats = PushStats::new(packets).ok_or("no packets to write")?;
+
+        let file = File::create(output_path).map_err(gst_utils::err)?;
+        let mut writer = BufWriter::new(file);
+        let report_every = (stats.expected / 100).max(1);
+
+        for packet in packets {
+            if self.is_cancelled() {
+                return Err(CANCELLED.to_string());
+            }
+
+            writer.write_all(&packet.data).map_err(gst_utils::err)?;
+            stats.record(&packet);
+
+            if stats.count % report_every == 0 {
+                on_progress(stats.pushed_fraction());
+            }
+        }
+
+        if self.is_cancelled() {
+            return Err(CANCELLED.to_string());
+        }
+
+        writer.flush().map_err(gst_utils::err)?;
+        on_progress(1.0);
+
+        write_sidecar_if_needed(
+            output_path,
+            ClipSidecar {
+                markers: clip_markers(stats.first_dts_ns, self.markers),
+                metadata: self.metadata.cloned(),
+            },
+        )?;
+
+        Ok(RemuxResult {
+            duration_ms: stats.span_ns() / 1_000_000,
+            bytes_written: file_size(output_path)?,
+            pushed_bytes: stats.bytes,
+        })
+    }
+}
+
+// Running totals for the packets a job has consumed from its source.
+struct PushStats {
+    // Upper bound on the packet count when the source knows one, otherwise 0.
+    expected: usize,
+    count: usize,
+    bytes: u64,
+    first_dts_ns: u64,
+    last_dts_ns: u64,
+}
+
+impl PushStats {
+    // None when the source is empty.
+    fn new(packets: &mut Peekable<impl Iterator<Item = Packet>>) -> Option<Self> {
+        // Snapshots may skip unreadable payloads, so only promise an upper bound.
+        let expected = packets.size_hint().1.unwrap_or(0);
+        let first_dts_ns = packets.peek()?.dts_ns;
+
+        Some(Self {
+            expected,
+            count: 0,
+            bytes: 0,
+            first_dts_ns,
+            last_dts_ns: first_dts_ns,
+        })
+    }
+
+    fn record(&mut self, packet: &Packet) {
+        self.count += 1;
+        self.bytes += packet.data.len() as u64;
+        self.last_dts_ns = self.last_dts_ns.max(packet.dts_ns);
+    }
+
+    fn pushed_fraction(&self) -> f32 {
+        if self.expected == 0 {
+            return 0.0;
+        }
+
+        (self.count as f32 / self.expected as f32).min(1.0)
+    }
+
+    fn span_ns(&self) -> u64 {
+        self.last_dts_ns - self.first_dts_ns
+    }
+}
+
+/// Writes the packets' TS bytes straight to disk. Callers should pass packets
+/// that start on a keyframe, e.g. from `RingBuffer::snapshot_from_keyframe`.
+/// Metadata only goes to the sidecar.
+pub fn dump_ts(
+    packets: &[Packet],
+    markers: &[Marker],
+    metadata: Option<&ClipMetadata>,
+    output_path: &Path,
+) -> Result<RemuxResult, String> {
+    RemuxJob {
+        markers,
+        metadata,
+        format: ClipFormat::Ts,
+        cancel: None,
+    }
+    .run(packets, output_path, |_| {})
+}
+
+// How much of the clip the muxer has written, from its position query.
+fn muxed_fraction(mux: &gst::Element, duration_ns: u64) -> f32 {
+    mux.query_position::<gst::ClockTime>()
+        .map(|position| (position.nseconds() as f32 / duration_ns.max(1) as f32).min(1.0))
+        .unwrap_or(0.0)
+}
+
+// Non-live appsrc for ring buffer TS packets, fed through `push_packets`.
+fn make_ts_appsrc() -> Result<gst_app::AppSrc, String> {
+    let appsrc = gst_utils::make("appsrc")?
+        .downcast::<gst_app::AppSrc>()
+        .map_err(|_| "failed to downcast appsrc")?;
+
+    appsrc.set_property("is-live", false);
+    appsrc.set_property("format", gst::Format::Time);
+    appsrc.set_property("block", false);
+    appsrc.set_property("max-bytes", APPSRC_MAX_BYTES);
+
+    let ts_caps = gst::Caps::builder("video/mpegts")
+        .field("systemstream", true)
+        .field("packetsize", 188i32)
+        .build();
+
+    appsrc.set_caps(Some(&ts_caps));
+
+    Ok(appsrc)
+}
+
+// Pushes `packets` into `appsrc` whenever it signals need-data, then sends
+// EOS. Waiting for need-data (rather than a blocking push) keeps the loop
+// responsive to cancellation and to pipeline errors, and means packets are
+// only pulled from the source as fast as the muxer consumes them.
+fn push_packets(
+    appsrc: &gst_app::AppSrc,
+    pipeline: &gst::Pipeline,
+    packets: &mut dyn Iterator<Item = Packet>,
+    cancel: Option<&CancelToken>,
+    on_push: &mut dyn FnMut(&Packet),
+) -> Result<(), String> {
+    fn set_gate(gate: &(Mutex<bool>, Condvar), open: bool) {
+        *gate.0.lock().unwrap() = open;
+        gate.1.notify_all();
+    }
+
+    let bus = pipeline.bus().ok_or("missing bus")?;
+    // (wants data, signal); the queue starts empty.
+    let gate = Arc::new((Mutex::new(true), Condvar::new()));
+
+    appsrc.set_callbacks(
+        gst_app::AppSrcCallbacks::builder()
+            .need_data({
+                let gate = gate.clone();
+                move |_, _| set_gate(&gate, true)
+            })
+            .enough_data({
+                let gate = gate.clone();
+                move |_| set_gate(&gate, false)
+            })
+            .build(),
+    );
+
+    for packet in packets {
+        let waiting_since = Instant::now();
+
+        loop {
+            if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+                return Err(CANCELLED.to_string());
+            }
+
+            if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+                if let gst::MessageView::Error(err) = msg.view() {
+                    return Err(err.error().to_string());
+                }
+            }
+
+            let (wants_data, signal) = &*gate;
+            let (open, _) = signal
+                .wait_timeout_while(
+                    wants_data.lock().unwrap(),
+                    Duration::from_millis(100),
+                    |open| !*open,
+                )
+                .unwrap();
+
+            if *open {
+                break;
+            }
+
+            if waiting_since.elapsed() > STALL_TIMEOUT {
+                return Err("remux stalled waiting for the muxer".to_string());
+            }
+        }
+
+        appsrc
+            .push_buffer(packet_buffer(&packet))
+            .map_err(gst_utils::err)?;
+        on_push(&packet);
+    }
+
+    // A source that blocks between packets (a post-roll) may end because of
+    // the cancellation; don't finish the clip in that case.
+    if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+        return Err(CANCELLED.to_string());
+    }
+
+    appsrc.end_of_stream().map(|_| ()).map_err(gst_utils::err)
+}
+
+fn packet_buffer(packet: &Packet) -> gst::Buffer {
+    // Wraps the shared payload without copying it.
+    let mut buffer = gst::Buffer::from_slice(packet.data.clone());
+
+    {
+        let buffer_ref = buffer.make_mut();
+
+        buffer_ref.set_pts(gst::ClockTime::from_nseconds(packet.pts_ns));
+        buffer_ref.set_dts(gst::ClockTime::from_nseconds(packet.dts_ns));
+        buffer_ref.set_duration(packet.duration_ns.map(gst::ClockTime::from_nseconds));
+
+        if packet.discont {
+            buffer_ref.set_flags(gst::BufferFlags::DISCONT);
+        }
+        if !packet.keyframe {
+            buffer_ref.set_flags(gst::BufferFlags::DELTA_UNIT);
+        }
+    }
+
+    buffer
+}
+
+// Blocks until EOS and shuts the pipeline down.
+fn wait_for_eos(pipeline: &gst::Pipeline) -> Result<(), String> {
+    watch_pipeline(pipeline, None, &mut |_| {})
+}
+
+// Blocks until EOS and shuts the pipeline down, reporting position / duration
+// to `on_progress`. Fails on errors, on cancellation, or when the pipeline
+// stalls; a quiet bus is fine while the position keeps moving.
+fn watch_pipeline(
+    pipeline: &gst::Pipeline,
+    cancel: Option<&CancelToken>,
+    on_progress: &mut dyn FnMut(f32),
+) -> Result<(), String> {
+    let bus = pipeline.bus().ok_or("missing bus")?;
+    let mut last_position = None;
+    let mut last_activity = Instant::now();
+
+    let result = loop {
+        if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+            break Err(CANCELLED.to_string());
+        }
+
+        if let Some(msg) = bus.timed_pop(gst::ClockTime::from_mseconds(100)) {
+            last_activity = Instant::now();
+
+            match msg.view() {
+                gst::MessageView::Eos(..) => break Ok(()),
+                gst::MessageView::Error(err) => break Err(err.error().to_string()),
+                _ => {}
+            }
+        }
+
+        let position = pipeline.query_position::<gst::ClockTime>();
+        if position.is_some() && position != last_position {
+            last_position = position;
+            last_activity = Instant::now();
+
+            if let (Some(position), Some(duration)) =
+                (position, pipeline.query_duration::<gst::ClockTime>())
+            {
+                let progress = position.nseconds() as f32 / duration.nseconds().max(1) as f32;
+                on_progress(progress.min(1.0));
+            }
+        }
+
+        if last_activity.elapsed() > STALL_TIMEOUT {
+            break Err("remux timed out waiting for EOS".to_string());
+        }
+    };
+
+    pipeline.set_state(gst::State::Null).ok();
+    result
+}
+
+// "video" or "audio" for a demuxer/parsebin src pad, from its current caps.
+fn pad_kind(pad: &gst::Pad) -> Option<&'static str> {
+    let caps = pad.current_caps()?;
+    let name = caps.structure(0)?.name();
+
+    if name.starts_with("video/") {
+        Some("video")
+    } else if name.starts_with("audio/") {
+        Some("audio")
+    } else {
+        None
+    }
+}
+
+// Requests a muxer pad for `kind` and links `element`'s src pad to it. Safe to
+// call from pad-added, before any data reaches the muxer.
+fn link_to_mux(
+    mux: &gst::Element,
+    format: ClipFormat,
+    kind: &str,
+    element: &gst::Element,
+) -> Result<(), String> {
+    let mux_pad = mux
+        .request_pad_simple(format.pad_template(kind))
+        .ok_or_else(|| format!("failed to request muxer {} pad", kind))?;
+
+    element
+        .static_pad("src")
+        .ok_or_else(|| format!("missing {} src pad", element.name()))?
+        .link(&mux_pad)
+        .map_err(gst_utils::err)?;
+
+    Ok(())
+}
+
+// Decoded video -> videoconvert (-> d3d11upload) -> encoder chain. Returns the
+// element whose src pad carries byte-stream H.264.
+fn reencode_video(
+    pipeline: &gst::Pipeline,
+    encoder: &VideoEncoder,
+    input: &gst::Element,
+) -> Result<gst::Element, String> {
+    let convert = gst_utils::make("videoconvert")?;
+    pipeline.add(&convert).map_err(gst_utils::err)?;
+    input.link(&convert).map_err(gst_utils::err)?;
+
+    let requires_d3d11 = encoders::find_video_encoder(encoder.encoder_id())
+        .map_err(gst_utils::err)?
+        .and_then(|descriptor| descriptor.required_memory)
+        .as_deref()
+        == Some("D3D11Memory");
+
+    let encoder_input = if requires_d3d11 {
+        let upload = gst_utils::make("d3d11upload")?;
+        pipeline.add(&upload).map_err(gst_utils::err)?;
+        convert.link(&upload).map_err(gst_utils::err)?;
+        upload
+    } else {
+        convert
+    };
+
+    let output = encoder
+        .build(
+            pipeline,
+            GraphOutput {
+                element: encoder_input,
+            },
+        )
+        .map_err(gst_utils::err)?;
+
+    Ok(output.element)
+}
+
+fn file_size(path: &Path) -> Result<u64, String> {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(gst_utils::err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ring_buffer::StreamId;
+
+    fn packet(dts_ms: u64) -> Packet {
+        Packet {
+            stream_id: StreamId::Video,
+            pts_ns: dts_ms * 1_000_000,
+            dts_ns: dts_ms * 1_000_000,
+            duration_ns: None,
+            keyframe: false,
+            discont: false,
+            data: vec![0; 188].into(),
+        }
+    }
+
+    const ALL_FORMATS: [ClipFormat; 4] = [
+        ClipFormat::Mp4,
+        ClipFormat::Mkv,
+        ClipFormat::FragmentedMp4,
+        ClipFormat::Ts,
+    ];
+
+    fn marker(label: &str, offset_ms: u64) -> ClipMarker {
+        ClipMarker {
+            label: label.to_string(),
+            offset_ms,
+        }
+    }
+
+    #[test]
+    fn clip_format_extensions_round_trip_through_from_path() {
+        for format in ALL_FORMATS {
+            let path = PathBuf::from(format!("clip.{}", format.extension()));
+            let expected = match format {
+                ClipFormat::FragmentedMp4 => ClipFormat::Mp4,
+                format => format,
+            };
+
+            assert_eq!(ClipFormat::from_path(&path), Some(expected));
+        }
+
+        assert_eq!(ClipFormat::from_path(Path::new("clip.gif")), None);
+        assert_eq!(ClipFormat::from_path(Path::new("clip")), None);
+    }
+
+    #[test]
+    fn clip_format_picks_muxer_and_pad_templates() {
+        let expected = [
+            ("mp4mux", "video_%u", "audio_%u"),
+            ("matroskamux", "video_%u", "audio_%u"),
+            ("mp4mux", "video_%u", "audio_%u"),
+            ("mpegtsmux", "sink_%d", "sink_%d"),
+        ];
+
+        for (format, (factory, video, audio)) in ALL_FORMATS.into_iter().zip(expected) {
+            assert_eq!(format.muxer_factory(), factory, "{:?}", format);
+            assert_eq!(format.pad_template("video"), video, "{:?}", format);
+            assert_eq!(format.pad_template("audio"), audio, "{:?}", format);
+        }
+
+        let chapters: Vec<bool> = ALL_FORMATS
+            .iter()
+            .map(ClipFormat::supports_chapters)
+            .collect();
+        assert_eq!(chapters, vec![false, true, false, false]);
+    }
+
+    #[test]
+    fn make_muxer_configures_mp4_variants() {
+        gst::init().unwrap();
+
+        // The muxer plugins are optional in test environments.
+        if gst::ElementFactory::find("mp4mux").is_none() {
+            return;
+        }
+
+        let mp4 = ClipFormat::Mp4.make_muxer().unwrap();
+        assert!(mp4.property::<bool>("faststart"));
+
+        let fragmented = ClipFormat::FragmentedMp4.make_muxer().unwrap();
+        assert!(fragmented.property::<bool>("streamable"));
+        assert_eq!(
+            fragmented.property::<u32>("fragment-duration"),
+            FRAGMENT_DURATION_MS
+        );
+    }
+
+    #[test]
+    fn clip_markers_are_offsets_from_the_first_packet() {
+        let markers = [
+            Marker {
+                label: "early".to_string(),
+                dts_ns: 500_000_000,
+            },
+            Marker {
+                label: "late".to_string(),
+                dts_ns: 3_250_000_000,
+            },
+        ];
+
+        let saved = clip_markers(1_000_000_000, &markers);
+
+        assert_eq!(saved[0].offset_ms, 0);
+        assert_eq!(saved[1].label, "late");
+        assert_eq!(saved[1].offset_ms, 2250);
+    }
+
+    #[test]
+    fn markers_toc_has_one_chapter_per_marker() {
+        gst::init().unwrap();
+
+        let toc = markers_toc(&[
+            marker("start", 0),
+            marker("goal", 1500),
+            marker("end", 4000),
+        ]);
+
+        let editions = toc.entries();
+        assert_eq!(editions.len(), 1);
+        assert_eq!(editions[0].entry_type(), gst::TocEntryType::Edition);
+
+        let chapters = editions[0].sub_entries();
+        let times: Vec<Option<(i64, i64)>> = chapters
+            .iter()
+            .map(|chapter| chapter.start_stop_times())
+            .collect();
+        // Each chapter runs to the next marker; the last one is open-ended.
+        assert_eq!(
+            times,
+            vec![
+                Some((0, 1_500_000_000)),
+                Some((1_500_000_000, 4_000_000_000)),
+                Some((4_000_000_000, -1)),
+            ]
+        );
+
+        let titles: Vec<String> = chapters
+            .iter()
+            .map(|chapter| {
+                chapter
+                    .tags()
+                    .and_then(|tags| tags.get::<gst::tags::Title>())
+                    .map(|title| title.get().to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+        assert_eq!(titles, vec!["start", "goal", "end"]);
+        assert_eq!(chapters[1].uid(), "chapter-1");
+    }
+
+    #[test]
+    fn markers_toc_without_markers_is_an_empty_edition() {
+        gst::init().unwrap();
+
+        let toc = markers_toc(&[]);
+
+        assert_eq!(toc.entries().len(), 1);
+        assert!(toc.entries()[0].sub_entries().is_empty());
+    }
+
+    #[test]
+    fn push_stats_is_none_for_an_empty_source() {
+        let mut packets = Vec::<Packet>::new().into_iter().peekable();
+
+        assert!(PushStats::new(&mut packets).is_none());
+    }
+
+    #[test]
+    fn push_stats_tracks_count_fraction_and_span() {
+        let source = vec![packet(100), packet(200), packet(150), packet(400)];
+        let mut packets = source.clone().into_iter().peekable();
+        let mut stats = PushStats::new(&mut packets).unwrap();
+
+        assert_eq!(stats.expected, 4);
+        assert_eq!(stats.pushed_fraction(), 0.0);
+        assert_eq!(stats.span_ns(), 0);
+
+        for packet in &source[..2] {
+            stats.record(packet);
+        }
+        assert_eq!(stats.pushed_fraction(), 0.5);
+        assert_eq!(stats.span_ns(), 100_000_000);
+
+        // Out-of-order DTS never shrinks the span.
+        for packet in &source[2..] {
+            stats.record(packet);
+        }
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.bytes, 4 * 188);
+        assert_eq!(stats.pushed_fraction(), 1.0);
+        assert_eq!(stats.span_ns(), 300_000_000);
+    }
+
+    #[test]
+    fn push_stats_uses_the_upper_bound_of_a_filtered_source() {
+        let source = vec![packet(0), packet(10), packet(20), packet(30)];
+        let mut packets = source
+            .into_iter()
+            .filter(|packet| packet.dts_ns != 10_000_000)
+            .peekable();
+        let mut stats = PushStats::new(&mut packets).unwrap();
+
+        assert_eq!(stats.expected, 4);
+
+        for packet in packets {
+            stats.record(&packet);
+        }
+        // Fewer packets than promised still ends below 1.0, never above.
+        assert_eq!(stats.pushed_fraction(), 0.75);
+    }
+
+    #[test]
+    fn push_stats_without_a_size_bound_reports_no_fraction() {
+        let mut packets = std::iter::successors(Some(packet(0)), |last| {
+            Some(packet(last.dts_ns / 1_000_000 + 10))
+        })
+        .peekable();
+        let mut stats = PushStats::new(&mut packets).unwrap();
+
+        assert_eq!(stats.expected, 0);
+
+        for packet in packets.take(3) {
+            stats.record(&packet);
+        }
+        assert_eq!(stats.pushed_fraction(), 0.0);
+        assert_eq!(stats.span_ns(), 20_000_000);
+    }
+}
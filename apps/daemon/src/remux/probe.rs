@@ -0,0 +1,88 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use gst::prelude::*;
+use gstreamer as gst;
+
+use super::pad_kind;
+use crate::gst_utils;
+
+/// Streams of a saved clip as parsebin exposes them, before any decoding.
+#[derive(Debug, Clone, Default)]
+pub struct ClipStreams {
+    pub video: Option<gst::Caps>,
+    pub audio: Option<gst::Caps>,
+    pub duration_ms: Option<u64>,
+}
+
+/// Prerolls the clip into fakesinks and reports the first video and audio
+/// stream caps plus the container duration.
+pub fn probe_streams(path: &Path) -> Result<ClipStreams, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    let pipeline = gst::Pipeline::new();
+
+    let filesrc = gst_utils::make("filesrc")?;
+    let parsebin = gst_utils::make("parsebin")?;
+
+    let location = path.to_str().ok_or("clip path is not valid UTF-8")?;
+    filesrc.set_property("location", location);
+
+    pipeline
+        .add_many([&filesrc, &parsebin])
+        .map_err(gst_utils::err)?;
+    filesrc.link(&parsebin).map_err(gst_utils::err)?;
+
+    let streams = Arc::new(Mutex::new(ClipStreams::default()));
+    let streams_clone = streams.clone();
+    let pipeline_weak = pipeline.downgrade();
+
+    parsebin.connect_pad_added(move |_, src_pad| {
+        let Some(pipeline) = pipeline_weak.upgrade() else {
+            return;
+        };
+        let Ok(fakesink) = gst_utils::make("fakesink") else {
+            return;
+        };
+
+        if pipeline.add(&fakesink).is_err() || fakesink.sync_state_with_parent().is_err() {
+            return;
+        }
+        if let Some(sink) = fakesink.static_pad("sink") {
+            let _ = src_pad.link(&sink);
+        }
+
+        let (Some(kind), Some(caps)) = (pad_kind(src_pad), src_pad.current_caps()) else {
+            return;
+        };
+
+        if let Ok(mut streams) = streams_clone.lock() {
+            let slot = match kind {
+                "video" => &mut streams.video,
+                _ => &mut streams.audio,
+            };
+            slot.get_or_insert(caps);
+        }
+    });
+
+    let prerolled = pipeline
+        .set_state(gst::State::Paused)
+        .map_err(gst_utils::err)
+        .and_then(|_| {
+            pipeline
+                .state(gst::ClockTime::from_seconds(5))
+                .0
+                .map_err(|_| format!("failed to read {}", path.display()))
+        });
+
+    let duration = pipeline.query_duration::<gst::ClockTime>();
+    pipeline.set_state(gst::State::Null).ok();
+    prerolled?;
+
+    let mut streams = streams.lock().map_err(gst_utils::err)?.clone();
+    streams.duration_ms = duration.map(|duration| duration.mseconds());
+
+    Ok(streams)
+}
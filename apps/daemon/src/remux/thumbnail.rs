@@ -0,0 +1,514 @@
+use std::path::{Path, PathBuf};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use serde::{Deserialize, Serialize};
+
+use super::{pad_kind, trim::index_clip, wait_for_eos, STALL_TIMEOUT};
+use crate::gst_utils;
+
+const POSTER_SUFFIX: &str = "thumb";
+const CONTACT_SHEET_SUFFIX: &str = "sheet";
+const DEFAULT_WIDTH: u32 = 320;
+// Frames are pulled as RGBx so rows are never padded.
+const BYTES_PER_PIXEL: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Png => "png",
+        }
+    }
+}
+
+/// Which frame becomes the poster.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PosterFrame {
+    /// The keyframe with the most detail, which skips fades and motion blur.
+    #[default]
+    SharpestKeyframe,
+    /// The first frame at or after this offset from the start of the clip.
+    OffsetMs(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThumbnailOptions {
+    #[serde(default)]
+    pub format: ThumbnailFormat,
+    #[serde(default)]
+    pub poster: PosterFrame,
+    /// Width of the poster and of each contact sheet tile.
+    #[serde(default = "default_width")]
+    pub width: u32,
+    /// Number of evenly spaced frames for a contact sheet, if any.
+    #[serde(default)]
+    pub contact_sheet_frames: Option<u32>,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            format: ThumbnailFormat::default(),
+            poster: PosterFrame::default(),
+            width: default_width(),
+            contact_sheet_frames: None,
+        }
+    }
+}
+
+fn default_width() -> u32 {
+    DEFAULT_WIDTH
+}
+
+pub struct Thumbnails {
+    pub poster: PathBuf,
+    pub contact_sheet: Option<PathBuf>,
+}
+
+/// Poster path for a clip, e.g. `clip-….mp4` -> `clip-….thumb.jpg`.
+pub fn thumbnail_path(clip_path: &Path, format: ThumbnailFormat) -> PathBuf {
+    image_path(clip_path, POSTER_SUFFIX, format)
+}
+
+/// The poster next to `clip_path` in any supported format, if one was written.
+pub fn find_thumbnail(clip_path: &Path) -> Option<PathBuf> {
+    [ThumbnailFormat::Jpeg, ThumbnailFormat::Png]
+        .into_iter()
+        .map(|format| thumbnail_path(clip_path, format))
+        .find(|path| path.is_file())
+}
+
+/// Whether `path` is a poster or contact sheet rather than a clip.
+pub fn is_thumbnail(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|stem| Path::new(stem).extension())
+        .is_some_and(|suffix| suffix == POSTER_SUFFIX || suffix == CONTACT_SHEET_SUFFIX)
+}
+
+fn image_path(clip_path: &Path, suffix: &str, format: ThumbnailFormat) -> PathBuf {
+    clip_path.with_extension(format!("{}.{}", suffix, format.extension()))
+}
+
+#[derive(Clone)]
+struct Frame {
+    pts_ns: u64,
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+/// Decodes the clip once and writes the poster (and optional contact sheet)
+/// next to it.
+pub fn generate_thumbnails(
+    clip_path: &Path,
+    options: &ThumbnailOptions,
+) -> Result<Thumbnails, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    let index = index_clip(clip_path)?;
+    let origin_ns = index.origin_ns.ok_or("clip has no video frames")?;
+    let duration_ns = index.end_ns.saturating_sub(origin_ns);
+
+    // Frame times wanted for the contact sheet, evenly spread over the clip.
+    let sheet_targets: Vec<u64> = match options.contact_sheet_frames {
+        Some(count) if count > 0 => (0..u64::from(count))
+            .map(|i| origin_ns + (2 * i + 1) * duration_ns / (2 * u64::from(count)))
+            .collect(),
+        _ => Vec::new(),
+    };
+    // Decoded frames carry PTS, so match them against the keyframes' PTS.
+    let keyframes = &index.keyframe_pts;
+
+    let mut poster: Option<(Frame, f64)> = None;
+    let mut sheet: Vec<Frame> = Vec::new();
+
+    decode_frames(clip_path, options.width, |frame| {
+        match options.poster {
+            PosterFrame::OffsetMs(offset_ms) => {
+                if poster.is_none() && frame.pts_ns >= origin_ns + offset_ms * 1_000_000 {
+                    poster = Some((frame.clone(), 0.0));
+                }
+            }
+            PosterFrame::SharpestKeyframe => {
+                if keyframes.binary_search(&frame.pts_ns).is_ok() {
+                    let score = sharpness(frame);
+                    if poster.as_ref().is_none_or(|(_, best)| score > *best) {
+                        poster = Some((frame.clone(), score));
+                    }
+                }
+            }
+        }
+
+        if let Some(target) = sheet_targets.get(sheet.len()) {
+            if frame.pts_ns >= *target {
+                sheet.push(frame.clone());
+            }
+        }
+
+        // Keep decoding only while something still needs frames.
+        let poster_done = matches!(options.poster, PosterFrame::OffsetMs(_)) && poster.is_some();
+        !(poster_done && sheet.len() >= sheet_targets.len())
+    })?;
+
+    let (poster, _) = poster.ok_or("no frame found for the poster")?;
+    let poster_path = thumbnail_path(clip_path, options.format);
+    write_image(&poster, options.format, &poster_path)?;
+
+    let contact_sheet = if sheet.is_empty() {
+        None
+    } else {
+        let path = image_path(clip_path, CONTACT_SHEET_SUFFIX, options.format);
+        write_image(&tile_frames(&sheet), options.format, &path)?;
+        Some(path)
+    };
+
+    Ok(Thumbnails {
+        poster: poster_path,
+        contact_sheet,
+    })
+}
+
+// Decodes every video frame, scaled to `width`, until `on_frame` returns false.
+fn decode_frames(
+    clip_path: &Path,
+    width: u32,
+    mut on_frame: impl FnMut(&Frame) -> bool,
+) -> Result<(), String> {
+    let pipeline = gst::Pipeline::new();
+
+    let filesrc = gst_utils::make("filesrc")?;
+    let decodebin = gst_utils::make("decodebin")?;
+    let queue = gst_utils::make("queue")?;
+    let convert = gst_utils::make("videoconvert")?;
+    let videoscale = gst_utils::make("videoscale")?;
+    let appsink = gst_utils::make("appsink")?
+        .downcast::<gst_app::AppSink>()
+        .map_err(|_| "failed to downcast appsink")?;
+
+    let location = clip_path.to_str().ok_or("clip path is not valid UTF-8")?;
+    filesrc.set_property("location", location);
+
+    appsink.set_property("sync", false);
+    appsink.set_caps(Some(
+        &gst::Caps::builder("video/x-raw")
+            .field("format", "RGBx")
+            .field("width", (width.max(16) & !1) as i32)
+            .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+            .build(),
+    ));
+
+    pipeline
+        .add_many([
+            &filesrc,
+            &decodebin,
+            &queue,
+            &convert,
+            &videoscale,
+            appsink.upcast_ref(),
+        ])
+        .map_err(gst_utils::err)?;
+
+    filesrc.link(&decodebin).map_err(gst_utils::err)?;
+    gst::Element::link_many([&queue, &convert, &videoscale, appsink.upcast_ref()])
+        .map_err(gst_utils::err)?;
+
+    let queue_sink = queue.static_pad("sink").ok_or("missing queue sink pad")?;
+    let pipeline_weak = pipeline.downgrade();
+
+    // Audio is decoded into a fakesink so the demuxer keeps going.
+    decodebin.connect_pad_added(move |_, src_pad| match pad_kind(src_pad) {
+        Some("video") if !queue_sink.is_linked() => {
+            let _ = src_pad.link(&queue_sink);
+        }
+        _ => {
+            let Some(pipeline) = pipeline_weak.upgrade() else {
+                return;
+            };
+            let Ok(fakesink) = gst_utils::make("fakesink") else {
+                return;
+            };
+
+            fakesink.set_property("sync", false);
+
+            if pipeline.add(&fakesink).is_ok() && fakesink.sync_state_with_parent().is_ok() {
+                if let Some(sink) = fakesink.static_pad("sink") {
+                    let _ = src_pad.link(&sink);
+                }
+            }
+        }
+    });
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    let timeout = gst::ClockTime::from_nseconds(STALL_TIMEOUT.as_nanos() as u64);
+    let mut result = Ok(());
+
+    while let Some(sample) = appsink.try_pull_sample(timeout) {
+        let Some(frame) = sample_frame(&sample) else {
+            continue;
+        };
+
+        if !on_frame(&frame) {
+            break;
+        }
+    }
+
+    if let Some(bus) = pipeline.bus() {
+        if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+            if let gst::MessageView::Error(err) = msg.view() {
+                result = Err(err.error().to_string());
+            }
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).ok();
+    result
+}
+
+fn sample_frame(sample: &gst::Sample) -> Option<Frame> {
+    let structure = sample.caps()?.structure(0)?;
+    let width = structure.get::<i32>("width").ok()? as usize;
+    let height = structure.get::<i32>("height").ok()? as usize;
+
+    let buffer = sample.buffer()?;
+    let map = buffer.map_readable().ok()?;
+
+    if map.len() < width * height * BYTES_PER_PIXEL {
+        return None;
+    }
+
+    Some(Frame {
+        pts_ns: buffer.pts()?.nseconds(),
+        width,
+        height,
+        data: map[..width * height * BYTES_PER_PIXEL].to_vec(),
+    })
+}
+
+// Variance of the Laplacian over luma; higher means more edges in focus.
+fn sharpness(frame: &Frame) -> f64 {
+    let luma = |x: usize, y: usize| {
+        let i = (y * frame.width + x) * BYTES_PER_PIXEL;
+        let (r, g, b) = (
+            frame.data[i] as i32,
+            frame.data[i + 1] as i32,
+            frame.data[i + 2] as i32,
+        );
+        (2 * r + 5 * g + b) / 8
+    };
+
+    let mut sum = 0f64;
+    let mut sum_sq = 0f64;
+    let mut count = 0f64;
+
+    for y in 1..frame.height.saturating_sub(1) {
+        for x in 1..frame.width.saturating_sub(1) {
+            let laplacian =
+                4 * luma(x, y) - luma(x - 1, y) - luma(x + 1, y) - luma(x, y - 1) - luma(x, y + 1);
+            let value = laplacian as f64;
+
+            sum += value;
+            sum_sq += value * value;
+            count += 1.0;
+        }
+    }
+
+    if count == 0.0 {
+        return 0.0;
+    }
+
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+// Lays frames out in a near-square grid, left to right, top to bottom.
+fn tile_frames(frames: &[Frame]) -> Frame {
+    let tile_width = frames[0].width;
+    let tile_height = frames[0].height;
+    let columns = (frames.len() as f64).sqrt().ceil() as usize;
+    let rows = frames.len().div_ceil(columns);
+
+    let width = tile_width * columns;
+    let height = tile_height * rows;
+    let mut data = vec![0u8; width * height * BYTES_PER_PIXEL];
+
+    for (index, frame) in frames.iter().enumerate() {
+        let left = (index % columns) * tile_width;
+        let top = (index / columns) * tile_height;
+        let row_bytes = tile_width.min(frame.width) * BYTES_PER_PIXEL;
+
+        for y in 0..tile_height.min(frame.height) {
+            let src = y * frame.width * BYTES_PER_PIXEL;
+            let dst = ((top + y) * width + left) * BYTES_PER_PIXEL;
+            data[dst..dst + row_bytes].copy_from_slice(&frame.data[src..src + row_bytes]);
+        }
+    }
+
+    Frame {
+        pts_ns: frames[0].pts_ns,
+        width,
+        height,
+        data,
+    }
+}
+
+// appsrc (one RGBx frame) -> videoconvert -> jpegenc/pngenc -> filesink
+fn write_image(frame: &Frame, format: ThumbnailFormat, path: &Path) -> Result<(), String> {
+    let pipeline = gst::Pipeline::new();
+
+    let appsrc = gst_utils::make("appsrc")?
+        .downcast::<gst_app::AppSrc>()
+        .map_err(|_| "failed to downcast appsrc")?;
+    let convert = gst_utils::make("videoconvert")?;
+    let encoder = gst_utils::make(match format {
+        ThumbnailFormat::Jpeg => "jpegenc",
+        ThumbnailFormat::Png => "pngenc",
+    })?;
+    let filesink = gst_utils::make("filesink")?;
+
+    appsrc.set_property("format", gst::Format::Time);
+    appsrc.set_caps(Some(
+        &gst::Caps::builder("video/x-raw")
+            .field("format", "RGBx")
+            .field("width", frame.width as i32)
+            .field("height", frame.height as i32)
+            .field("framerate", gst::Fraction::new(0, 1))
+            .build(),
+    ));
+
+    let location = path.to_str().ok_or("thumbnail path is not valid UTF-8")?;
+    filesink.set_property("location", location);
+
+    pipeline
+        .add_many([appsrc.upcast_ref(), &convert, &encoder, &filesink])
+        .map_err(gst_utils::err)?;
+    gst::Element::link_many([appsrc.upcast_ref(), &convert, &encoder, &filesink])
+        .map_err(gst_utils::err)?;
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    let mut buffer = gst::Buffer::from_mut_slice(frame.data.clone());
+    buffer.make_mut().set_pts(gst::ClockTime::ZERO);
+
+    appsrc.push_buffer(buffer).map_err(gst_utils::err)?;
+    appsrc.end_of_stream().map_err(gst_utils::err)?;
+
+    wait_for_eos(&pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An RGBx frame with every pixel from `pixel(x, y)` as a grey level.
+    fn frame(width: usize, height: usize, pixel: impl Fn(usize, usize) -> u8) -> Frame {
+        let mut data = Vec::with_capacity(width * height * BYTES_PER_PIXEL);
+        for y in 0..height {
+            for x in 0..width {
+                let value = pixel(x, y);
+                data.extend_from_slice(&[value, value, value, 0]);
+            }
+        }
+
+        Frame {
+            pts_ns: 0,
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn pixel(frame: &Frame, x: usize, y: usize) -> u8 {
+        frame.data[(y * frame.width + x) * BYTES_PER_PIXEL]
+    }
+
+    #[test]
+    fn flat_frames_have_no_sharpness() {
+        assert_eq!(sharpness(&frame(16, 16, |_, _| 128)), 0.0);
+        // Too small to have any interior pixels.
+        assert_eq!(sharpness(&frame(2, 2, |x, _| (x * 255) as u8)), 0.0);
+    }
+
+    #[test]
+    fn edges_are_sharper_than_gradients() {
+        let checkerboard = frame(16, 16, |x, y| if (x + y) % 2 == 0 { 255 } else { 0 });
+        let gradient = frame(16, 16, |x, _| (x * 16) as u8);
+        let blurred_edge = frame(16, 16, |x, _| match x {
+            0..=6 => 0,
+            7 => 64,
+            8 => 128,
+            9 => 192,
+            _ => 255,
+        });
+        let hard_edge = frame(16, 16, |x, _| if x < 8 { 0 } else { 255 });
+
+        assert!(sharpness(&checkerboard) > sharpness(&hard_edge));
+        assert!(sharpness(&hard_edge) > sharpness(&blurred_edge));
+        // A linear gradient has no second derivative inside the frame.
+        assert_eq!(sharpness(&gradient), 0.0);
+    }
+
+    #[test]
+    fn tiles_frames_into_a_near_square_grid() {
+        let frames: Vec<Frame> = (1..=3)
+            .map(|level| frame(4, 2, move |_, _| level * 50))
+            .collect();
+
+        let sheet = tile_frames(&frames);
+
+        // Three tiles fit a 2 x 2 grid; the last cell stays black.
+        assert_eq!((sheet.width, sheet.height), (8, 4));
+        assert_eq!(sheet.data.len(), 8 * 4 * BYTES_PER_PIXEL);
+        assert_eq!(pixel(&sheet, 0, 0), 50);
+        assert_eq!(pixel(&sheet, 7, 1), 100);
+        assert_eq!(pixel(&sheet, 3, 3), 150);
+        assert_eq!(pixel(&sheet, 4, 2), 0);
+        assert_eq!(pixel(&sheet, 7, 3), 0);
+    }
+
+    #[test]
+    fn tiles_are_cropped_to_the_first_frame_size() {
+        let frames = vec![
+            frame(2, 2, |_, _| 10),
+            frame(3, 3, |x, y| (x + 3 * y) as u8),
+        ];
+
+        let sheet = tile_frames(&frames);
+
+        assert_eq!((sheet.width, sheet.height), (4, 2));
+        assert_eq!(pixel(&sheet, 1, 1), 10);
+        assert_eq!(pixel(&sheet, 2, 0), 0);
+        assert_eq!(pixel(&sheet, 3, 1), 4);
+    }
+
+    #[test]
+    fn recognises_posters_and_contact_sheets() {
+        let clip = Path::new("/clips/clip-2024-01-01_12-00-00.mp4");
+
+        assert!(is_thumbnail(&thumbnail_path(clip, ThumbnailFormat::Jpeg)));
+        assert!(is_thumbnail(&image_path(
+            clip,
+            CONTACT_SHEET_SUFFIX,
+            ThumbnailFormat::Png
+        )));
+        assert!(!is_thumbnail(clip));
+        assert!(!is_thumbnail(Path::new("/clips/holiday.jpg")));
+        assert!(!is_thumbnail(Path::new("/clips/thumb.jpg")));
+        assert!(!is_thumbnail(Path::new("/clips/clip.thumbnail.jpg")));
+    }
+}
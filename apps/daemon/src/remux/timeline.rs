@@ -0,0 +1,556 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use gst::prelude::*;
+use gstreamer as gst;
+
+use crate::logger;
+
+// Gaps longer than this are flagged DISCONT but kept, so A/V stay in sync
+// across dropped frames.
+const GAP_TOLERANCE_NS: u64 = 50_000_000;
+// Gaps longer than this (e.g. a capture restart) are closed up instead.
+const MAX_GAP_NS: u64 = 1_000_000_000;
+// How long the first buffer of one stream waits for the other to show up
+// before the clip origin is picked without it.
+const ORIGIN_WAIT: Duration = Duration::from_millis(500);
+// How long a stream that hit a jump waits for the reference stream to close
+// the same jump before closing it up on its own.
+const JUMP_WAIT: Duration = Duration::from_millis(500);
+
+// Clip origin shared by all streams: the earliest first timestamp.
+struct Origin {
+    first_ns: Vec<Option<u64>>,
+    origin_ns: Option<u64>,
+}
+
+// Shifts of the jumps closed on the reference stream, in order, so every
+// stream moves by the same amount and A/V stay in sync.
+#[derive(Default)]
+struct Jumps {
+    shifts_ns: Vec<i64>,
+    reference_ended: bool,
+}
+
+// Per-stream repair state, in rebased nanoseconds.
+#[derive(Default)]
+struct StreamTimeline {
+    origin_ns: Option<u64>,
+    // Added to every timestamp after the origin is subtracted; moves when a
+    // jump is closed up.
+    shift_ns: i64,
+    last_ns: Option<u64>,
+    last_duration_ns: u64,
+    // (source offset from the origin, `shift_ns` from there on) per closed jump.
+    jumps: Vec<(u64, i64)>,
+}
+
+impl StreamTimeline {
+    // Maps a source timestamp onto the clean timeline. Returns the rebased
+    // timestamp and whether the buffer starts a discontinuity. At a jump,
+    // `jump_shift` gets the shift that would close it on this stream and
+    // returns the one to use.
+    fn map(
+        &mut self,
+        ts_ns: u64,
+        origin_ns: u64,
+        duration_ns: Option<u64>,
+        jump_shift: impl FnOnce(i64) -> i64,
+    ) -> (u64, bool) {
+        let mut mapped = shifted(ts_ns, origin_ns, self.shift_ns);
+        let mut discont = false;
+        let source_offset_ns = ts_ns.saturating_sub(origin_ns);
+
+        if let Some(last) = self.last_ns {
+            let expected = last + self.last_duration_ns;
+
+            if mapped + GAP_TOLERANCE_NS < last || mapped > expected + MAX_GAP_NS {
+                logger::warn(
+                    "remux",
+                    format!(
+                        "timestamp jump of {} ms in clip, closing it up",
+                        (mapped as i64 - expected as i64) / 1_000_000
+                    ),
+                );
+                self.shift_ns = jump_shift(self.shift_ns + expected as i64 - mapped as i64);
+                self.jumps.push((source_offset_ns, self.shift_ns));
+                mapped = shifted(ts_ns, origin_ns, self.shift_ns);
+                discont = true;
+            } else if mapped > expected + GAP_TOLERANCE_NS {
+                discont = true;
+            }
+
+            // A shared shift can leave a stream overlapping its own last
+            // buffer by up to a frame; lay such buffers after it.
+            if mapped < last {
+                mapped = expected;
+            }
+
+            self.last_duration_ns = duration_ns.unwrap_or(mapped - last);
+        } else {
+            self.last_duration_ns = duration_ns.unwrap_or(0);
+        }
+
+        self.last_ns = Some(mapped);
+        (mapped, discont)
+    }
+}
+
+fn shifted(ts_ns: u64, origin_ns: u64, shift_ns: i64) -> u64 {
+    (ts_ns as i64 - origin_ns as i64 + shift_ns).max(0) as u64
+}
+
+// Publishes a jump closed on the reference stream.
+fn publish_jump(jumps: &(Mutex<Jumps>, Condvar), shift_ns: i64) -> i64 {
+    jumps.0.lock().unwrap().shifts_ns.push(shift_ns);
+    jumps.1.notify_all();
+    shift_ns
+}
+
+// Lets the others stop waiting for jumps once the reference stream is done.
+fn end_reference(jumps: &(Mutex<Jumps>, Condvar)) {
+    jumps.0.lock().unwrap().reference_ended = true;
+    jumps.1.notify_all();
+}
+
+// Takes the shift of jump number `index` on the reference stream, waiting
+// for it when this stream got there first. Falls back to `own_shift_ns`
+// when the reference stream has no such jump.
+fn follow_jump(jumps: &(Mutex<Jumps>, Condvar), index: usize, own_shift_ns: i64) -> i64 {
+    let (state, _) = jumps
+        .1
+        .wait_timeout_while(jumps.0.lock().unwrap(), JUMP_WAIT, |state| {
+            state.shifts_ns.len() <= index && !state.reference_ended
+        })
+        .unwrap();
+
+    state.shifts_ns.get(index).copied().unwrap_or_else(|| {
+        logger::warn(
+            "remux",
+            "timestamp jump only in a secondary stream, closing it up on its own",
+        );
+        own_shift_ns
+    })
+}
+
+/// What `normalize_timestamps` did to the first (reference) stream, for
+/// placing markers and measuring the clip once the pipeline has finished.
+#[derive(Clone)]
+pub(super) struct TimelineReport(Arc<Mutex<StreamTimeline>>);
+
+impl TimelineReport {
+    /// Where a point `offset_ms` into the source, counted from the clip
+    /// start, landed after jumps before it were closed up.
+    pub(super) fn map_ms(&self, offset_ms: u64) -> u64 {
+        let timeline = self.0.lock().unwrap();
+        let offset_ns = offset_ms.saturating_mul(1_000_000);
+        let shift_ns = timeline
+            .jumps
+            .iter()
+            .take_while(|(at_ns, _)| *at_ns <= offset_ns)
+            .last()
+            .map_or(0, |(_, shift_ns)| *shift_ns);
+
+        (offset_ns as i64 + shift_ns).max(0) as u64 / 1_000_000
+    }
+
+    /// Total shift applied by the closed jumps; negative when gaps were removed.
+    pub(super) fn total_shift_ns(&self) -> i64 {
+        self.0.lock().unwrap().shift_ns
+    }
+
+    /// Last timestamp on the clean timeline, if the stream had any buffers.
+    pub(super) fn end_ns(&self) -> Option<u64> {
+        self.0.lock().unwrap().last_ns
+    }
+}
+
+/// Rebases the streams on `pads` so the clip starts at zero, with one origin
+/// for all of them to keep A/V sync. Backward jumps and long gaps are closed
+/// up by the same amount on every stream, as decided on `pads[0]`; shorter
+/// gaps are kept; both are flagged DISCONT. Segments are replaced with a
+/// fresh TIME segment to match. The pads should be on separate streaming
+/// threads, e.g. behind queues, since the first buffer of each stream and
+/// the first buffer of the others after a jump briefly wait for `pads[0]`.
+/// The report follows `pads[0]`.
+pub(super) fn normalize_timestamps(pads: &[gst::Pad]) -> TimelineReport {
+    let origin = Arc::new((
+        Mutex::new(Origin {
+            first_ns: vec![None; pads.len()],
+            origin_ns: None,
+        }),
+        Condvar::new(),
+    ));
+
+    let jumps = Arc::new((Mutex::new(Jumps::default()), Condvar::new()));
+    let reference = Arc::new(Mutex::new(StreamTimeline::default()));
+
+    for (index, pad) in pads.iter().enumerate() {
+        let origin = origin.clone();
+        let jumps = jumps.clone();
+        let timeline = match index {
+            0 => reference.clone(),
+            _ => Arc::new(Mutex::new(StreamTimeline::default())),
+        };
+
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_, info| {
+                let event_type = match info.data {
+                    Some(gst::PadProbeData::Event(ref event)) => Some(event.type_()),
+                    _ => None,
+                };
+
+                match event_type {
+                    Some(gst::EventType::Segment) => {
+                        let segment = gst::FormattedSegment::<gst::ClockTime>::new();
+                        info.data =
+                            Some(gst::PadProbeData::Event(gst::event::Segment::new(&segment)));
+                        return gst::PadProbeReturn::Ok;
+                    }
+                    // A stream that ends without data must not hold up the others.
+                    Some(gst::EventType::Eos) => {
+                        register_first(&origin, index, None);
+                        if index == 0 {
+                            end_reference(&jumps);
+                        }
+                        return gst::PadProbeReturn::Ok;
+                    }
+                    _ => {}
+                }
+
+                let Some(gst::PadProbeData::Buffer(ref mut buffer)) = info.data else {
+                    return gst::PadProbeReturn::Ok;
+                };
+
+                let Some(ts_ns) = buffer.dts_or_pts().map(|ts| ts.nseconds()) else {
+                    return gst::PadProbeReturn::Ok;
+                };
+
+                let mut timeline = timeline.lock().unwrap();
+                let origin_ns = *timeline.origin_ns.get_or_insert_with(|| {
+                    register_first(&origin, index, Some(ts_ns));
+                    wait_for_origin(&origin)
+                });
+
+                let duration_ns = buffer.duration().map(|duration| duration.nseconds());
+                let next_jump = timeline.jumps.len();
+                let (mapped_ns, discont) =
+                    timeline.map(ts_ns, origin_ns, duration_ns, |own_shift_ns| match index {
+                        0 => publish_jump(&jumps, own_shift_ns),
+                        _ => follow_jump(&jumps, next_jump, own_shift_ns),
+                    });
+                // Applied to PTS too, keeping the PTS - DTS reorder delay.
+                let shift_ns = mapped_ns as i64 - ts_ns as i64;
+
+                let buffer = buffer.make_mut();
+                let apply = |ts: gst::ClockTime| {
+                    gst::ClockTime::from_nseconds((ts.nseconds() as i64 + shift_ns).max(0) as u64)
+                };
+
+                buffer.set_pts(buffer.pts().map(apply));
+                buffer.set_dts(buffer.dts().map(apply));
+
+                if discont {
+                    buffer.set_flags(gst::BufferFlags::DISCONT);
+                }
+
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+
+    TimelineReport(reference)
+}
+
+// Records the first timestamp of stream `index` (None once it ends without
+// one) and fixes the origin when every stream has reported.
+fn register_first(origin: &(Mutex<Origin>, Condvar), index: usize, ts_ns: Option<u64>) {
+    let mut state = origin.0.lock().unwrap();
+
+    if state.origin_ns.is_some() || state.first_ns[index].is_some() {
+        return;
+    }
+
+    // Ended streams count as reported, at no particular time.
+    state.first_ns[index] = Some(ts_ns.unwrap_or(u64::MAX));
+
+    if state.first_ns.iter().all(Option::is_some) {
+        state.origin_ns = state.first_ns.iter().flatten().copied().min();
+        origin.1.notify_all();
+    }
+}
+
+// Blocks the calling streaming thread until the origin is known, or picks it
+// from the streams seen so far after `ORIGIN_WAIT`.
+fn wait_for_origin(origin: &(Mutex<Origin>, Condvar)) -> u64 {
+    let (mut state, _) = origin
+        .1
+        .wait_timeout_while(origin.0.lock().unwrap(), ORIGIN_WAIT, |state| {
+            state.origin_ns.is_none()
+        })
+        .unwrap();
+
+    if let Some(origin_ns) = state.origin_ns {
+        return origin_ns;
+    }
+
+    let origin_ns = state.first_ns.iter().flatten().copied().min().unwrap_or(0);
+    state.origin_ns = Some(origin_ns);
+    origin_ns
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+    const FRAME: u64 = 33 * MS;
+
+    // Closes jumps with the stream's own shift, as the reference stream does.
+    fn own(shift_ns: i64) -> i64 {
+        shift_ns
+    }
+
+    fn origin(streams: usize) -> (Mutex<Origin>, Condvar) {
+        (
+            Mutex::new(Origin {
+                first_ns: vec![None; streams],
+                origin_ns: None,
+            }),
+            Condvar::new(),
+        )
+    }
+
+    #[test]
+    fn rebases_to_zero_without_discont() {
+        let mut timeline = StreamTimeline::default();
+        let origin_ns = 10_000 * MS;
+
+        assert_eq!(
+            timeline.map(origin_ns, origin_ns, Some(FRAME), own),
+            (0, false)
+        );
+        assert_eq!(
+            timeline.map(origin_ns + FRAME, origin_ns, Some(FRAME), own),
+            (FRAME, false)
+        );
+        // A stream that starts after the origin keeps its offset.
+        let mut late = StreamTimeline::default();
+        assert_eq!(
+            late.map(origin_ns + 20 * MS, origin_ns, None, own),
+            (20 * MS, false)
+        );
+        assert_eq!(timeline.shift_ns, 0);
+    }
+
+    #[test]
+    fn short_gap_is_kept_and_flagged() {
+        let mut timeline = StreamTimeline::default();
+
+        timeline.map(0, 0, Some(FRAME), own);
+        // 200 ms of dropped frames: over the tolerance, well under MAX_GAP_NS.
+        assert_eq!(
+            timeline.map(FRAME + 200 * MS, 0, Some(FRAME), own),
+            (FRAME + 200 * MS, true)
+        );
+        // Jitter within the tolerance is not a discontinuity.
+        assert_eq!(
+            timeline.map(2 * FRAME + 200 * MS + 10 * MS, 0, Some(FRAME), own),
+            (2 * FRAME + 210 * MS, false)
+        );
+        assert_eq!(timeline.shift_ns, 0);
+        assert!(timeline.jumps.is_empty());
+    }
+
+    #[test]
+    fn long_gap_is_closed_up() {
+        let mut timeline = StreamTimeline::default();
+
+        timeline.map(0, 0, Some(FRAME), own);
+        timeline.map(FRAME, 0, Some(FRAME), own);
+
+        // A capture restart 5 s later continues right after the last frame.
+        let restart_ns = 5_000 * MS;
+        assert_eq!(
+            timeline.map(restart_ns, 0, Some(FRAME), own),
+            (2 * FRAME, true)
+        );
+        assert_eq!(
+            timeline.map(restart_ns + FRAME, 0, Some(FRAME), own),
+            (3 * FRAME, false)
+        );
+
+        let shift_ns = 2 * FRAME as i64 - restart_ns as i64;
+        assert_eq!(timeline.shift_ns, shift_ns);
+        assert_eq!(timeline.jumps, vec![(restart_ns, shift_ns)]);
+    }
+
+    #[test]
+    fn backward_jump_is_repaired() {
+        let mut timeline = StreamTimeline::default();
+
+        timeline.map(1_000 * MS, 0, Some(FRAME), own);
+        assert_eq!(
+            timeline.map(400 * MS, 0, Some(FRAME), own),
+            (1_000 * MS + FRAME, true)
+        );
+        assert_eq!(
+            timeline.map(400 * MS + FRAME, 0, Some(FRAME), own),
+            (1_000 * MS + 2 * FRAME, false)
+        );
+        assert_eq!(timeline.shift_ns, (600 * MS + FRAME) as i64);
+    }
+
+    // Video at 30 fps and audio in 20 ms frames up to a capture restart at 5 s,
+    // where audio comes back 10 ms after video.
+    const AUDIO: u64 = 20 * MS;
+    const RESTART: u64 = 5_000 * MS;
+
+    fn jumps() -> Arc<(Mutex<Jumps>, Condvar)> {
+        Arc::new((Mutex::new(Jumps::default()), Condvar::new()))
+    }
+
+    fn video_across_restart(video: &mut StreamTimeline, jumps: &(Mutex<Jumps>, Condvar)) {
+        video.map(0, 0, Some(FRAME), own);
+        video.map(FRAME, 0, Some(FRAME), own);
+        video.map(RESTART, 0, Some(FRAME), |shift_ns| {
+            publish_jump(jumps, shift_ns)
+        });
+    }
+
+    fn audio_across_restart(audio: &mut StreamTimeline, jumps: &(Mutex<Jumps>, Condvar)) -> u64 {
+        for frame in 0..4 {
+            audio.map(frame * AUDIO, 0, Some(AUDIO), own);
+        }
+        let next_jump = audio.jumps.len();
+        audio
+            .map(RESTART + 10 * MS, 0, Some(AUDIO), |shift_ns| {
+                follow_jump(jumps, next_jump, shift_ns)
+            })
+            .0
+    }
+
+    #[test]
+    fn streams_close_a_restart_by_the_same_shift() {
+        let jumps = jumps();
+        let mut video = StreamTimeline::default();
+        let mut audio = StreamTimeline::default();
+
+        video_across_restart(&mut video, &jumps);
+        let audio_ns = audio_across_restart(&mut audio, &jumps);
+
+        // Audio keeps its 10 ms lag behind video instead of closing up to its
+        // own last frame at 80 ms.
+        assert_eq!(video.shift_ns, 2 * FRAME as i64 - RESTART as i64);
+        assert_eq!(audio.shift_ns, video.shift_ns);
+        assert_eq!(audio_ns, 2 * FRAME + 10 * MS);
+    }
+
+    #[test]
+    fn stream_ahead_of_the_reference_waits_for_its_jump() {
+        let jumps = jumps();
+
+        let follower = {
+            let jumps = jumps.clone();
+            thread::spawn(move || {
+                let mut audio = StreamTimeline::default();
+                let started = Instant::now();
+                audio_across_restart(&mut audio, &jumps);
+                (audio.shift_ns, started.elapsed())
+            })
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        let mut video = StreamTimeline::default();
+        video_across_restart(&mut video, &jumps);
+
+        let (audio_shift_ns, waited) = follower.join().unwrap();
+        assert_eq!(audio_shift_ns, video.shift_ns);
+        assert!(waited < JUMP_WAIT);
+    }
+
+    #[test]
+    fn jump_missing_from_an_ended_reference_is_closed_on_its_own() {
+        let jumps = jumps();
+        end_reference(&jumps);
+
+        let mut audio = StreamTimeline::default();
+        let started = Instant::now();
+        let audio_ns = audio_across_restart(&mut audio, &jumps);
+
+        assert_eq!(audio_ns, 4 * AUDIO);
+        assert!(started.elapsed() < JUMP_WAIT);
+    }
+
+    #[test]
+    fn report_maps_offsets_past_closed_jumps() {
+        let timeline = Arc::new(Mutex::new(StreamTimeline::default()));
+        {
+            let mut timeline = timeline.lock().unwrap();
+            timeline.map(0, 0, Some(FRAME), own);
+            timeline.map(FRAME, 0, Some(FRAME), own);
+            timeline.map(5_000 * MS, 0, Some(FRAME), own);
+            timeline.map(5_000 * MS + FRAME, 0, Some(FRAME), own);
+        }
+        let report = TimelineReport(timeline);
+
+        assert_eq!(report.map_ms(20), 20);
+        // Past the 5 s restart, offsets move back by the removed gap.
+        assert_eq!(report.map_ms(5_000), 66);
+        assert_eq!(report.map_ms(5_100), 166);
+        assert_eq!(report.total_shift_ns(), 66 * MS as i64 - 5_000 * MS as i64);
+        assert_eq!(report.end_ns(), Some(3 * FRAME));
+    }
+
+    #[test]
+    fn origin_is_the_earliest_first_timestamp() {
+        let origin = origin(2);
+
+        register_first(&origin, 0, Some(5_000 * MS));
+        assert!(origin.0.lock().unwrap().origin_ns.is_none());
+        register_first(&origin, 1, Some(4_900 * MS));
+
+        assert_eq!(wait_for_origin(&origin), 4_900 * MS);
+        // Later reports don't move it.
+        register_first(&origin, 1, Some(0));
+        assert_eq!(wait_for_origin(&origin), 4_900 * MS);
+    }
+
+    #[test]
+    fn ended_stream_does_not_hold_up_the_origin() {
+        let origin = Arc::new(origin(2));
+
+        let waiter = {
+            let origin = origin.clone();
+            thread::spawn(move || {
+                register_first(&origin, 0, Some(7_000 * MS));
+                let started = Instant::now();
+                (wait_for_origin(&origin), started.elapsed())
+            })
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        register_first(&origin, 1, None);
+
+        let (origin_ns, waited) = waiter.join().unwrap();
+        assert_eq!(origin_ns, 7_000 * MS);
+        assert!(waited < ORIGIN_WAIT);
+    }
+
+    #[test]
+    fn origin_is_picked_without_a_silent_stream_after_the_wait() {
+        let origin = origin(2);
+
+        register_first(&origin, 0, Some(3_000 * MS));
+
+        let started = Instant::now();
+        assert_eq!(wait_for_origin(&origin), 3_000 * MS);
+        assert!(started.elapsed() >= ORIGIN_WAIT);
+        assert_eq!(origin.0.lock().unwrap().origin_ns, Some(3_000 * MS));
+    }
+}
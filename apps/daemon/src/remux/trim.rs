@@ -0,0 +1,595 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    file_size, link_to_mux, pad_kind, read_sidecar, reencode_video, wait_for_eos,
+    write_sidecar_if_needed, ClipFormat, ClipMarker, ClipSidecar, RemuxResult,
+};
+use crate::{gst_utils, logger, video::encoder::VideoEncoder};
+
+/// How the start of a trimmed clip is cut.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimMode {
+    /// Stream copy from the keyframe at or before `start_ms`, so the clip may
+    /// start up to one GOP early.
+    #[default]
+    Keyframe,
+    /// Starts exactly at `start_ms` by re-encoding the frames up to the next
+    /// keyframe; everything after that is still stream copied.
+    FrameAccurate,
+}
+
+// Keyframes and the video timeline of a saved clip, in the demuxer's timestamps.
+#[derive(Default)]
+pub(super) struct ClipIndex {
+    pub(super) origin_ns: Option<u64>,
+    pub(super) end_ns: u64,
+    pub(super) keyframes: Vec<u64>,
+    // Presentation times of the same keyframes, sorted; decoded frames carry
+    // these rather than the DTS above once B-frames reorder the stream.
+    pub(super) keyframe_pts: Vec<u64>,
+}
+
+// Where each part of the output comes from, in the demuxer's timestamps.
+struct TrimPlan {
+    // First keyframe copied, or the one the re-encoded GOP starts from.
+    keyframe_ns: u64,
+    // Output timeline zero.
+    start_ns: u64,
+    end_ns: u64,
+    // Next keyframe after `start_ns` when the leading GOP is re-encoded.
+    copy_from_ns: Option<u64>,
+}
+
+/// Writes `output_path` with the `[start_ms, end_ms)` range of `input_path`,
+/// measured from the clip's first video frame. The output container follows
+/// the output extension. `encoder` is only used by `TrimMode::FrameAccurate`.
+pub fn trim_clip(
+    input_path: &Path,
+    output_path: &Path,
+    start_ms: u64,
+    end_ms: u64,
+    mode: TrimMode,
+    encoder: &VideoEncoder,
+) -> Result<RemuxResult, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    if end_ms <= start_ms {
+        return Err("trim end must be after trim start".to_string());
+    }
+
+    let format = ClipFormat::from_path(output_path).ok_or("unsupported output extension")?;
+    let index = index_clip(input_path)?;
+    let plan = plan_trim(&index, start_ms, end_ms, mode)?;
+
+    let pipeline = gst::Pipeline::new();
+
+    // --- elements ---
+
+    let filesrc = gst_utils::make("filesrc")?;
+    let parsebin = gst_utils::make("parsebin")?;
+    let mux = format.make_muxer()?;
+    let filesink = gst_utils::make("filesink")?;
+    let audio_queue = gst_utils::make("queue")?;
+    let aacparse = gst_utils::make("aacparse")?;
+
+    // --- config ---
+
+    let input = input_path.to_str().ok_or("input path is not valid UTF-8")?;
+    filesrc.set_property("location", input);
+
+    let location = output_path
+        .to_str()
+        .ok_or("output path is not valid UTF-8")?;
+    filesink.set_property("location", location);
+
+    // --- pipeline assembly ---
+
+    pipeline
+        .add_many([
+            &filesrc,
+            &parsebin,
+            &mux,
+            &filesink,
+            &audio_queue,
+            &aacparse,
+        ])
+        .map_err(gst_utils::err)?;
+
+    filesrc.link(&parsebin).map_err(gst_utils::err)?;
+    mux.link(&filesink).map_err(gst_utils::err)?;
+    audio_queue.link(&aacparse).map_err(gst_utils::err)?;
+
+    let (video_entry, video_out) = match plan.copy_from_ns {
+        Some(copy_from_ns) => {
+            build_frame_accurate_video(&pipeline, &plan, copy_from_ns, format, encoder)?
+        }
+        None => build_copied_video(&pipeline, &plan)?,
+    };
+
+    attach_range_probe(
+        &pad(&audio_queue, "sink")?,
+        plan.start_ns,
+        plan.end_ns,
+        Some(plan.start_ns),
+    );
+
+    // --- dynamic pad handling ---
+
+    let mux_clone = mux.clone();
+
+    parsebin.connect_pad_added(move |_, src_pad| {
+        let Some(kind) = pad_kind(src_pad) else {
+            return;
+        };
+
+        let (entry, out) = match kind {
+            "video" => (&video_entry, &video_out),
+            _ => (&audio_queue, &aacparse),
+        };
+
+        let Some(sink) = entry.static_pad("sink") else {
+            return;
+        };
+
+        // Only the first stream of each kind is kept.
+        if sink.is_linked() || src_pad.link(&sink).is_err() {
+            return;
+        }
+
+        if let Err(err) = link_to_mux(&mux_clone, format, kind, out) {
+            logger::warn("remux", format!("trim: {}", err));
+        }
+    });
+
+    // --- run ---
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    wait_for_eos(&pipeline)?;
+
+    let start_ms = plan.start_ns.saturating_sub(index.origin_ns.unwrap_or(0)) / 1_000_000;
+    let end_ms = plan.end_ns.saturating_sub(index.origin_ns.unwrap_or(0)) / 1_000_000;
+
+    if let Some(sidecar) = read_sidecar(input_path) {
+        let markers: Vec<ClipMarker> = sidecar
+            .markers
+            .into_iter()
+            .filter(|marker| marker.offset_ms >= start_ms && marker.offset_ms < end_ms)
+            .map(|marker| ClipMarker {
+                offset_ms: marker.offset_ms - start_ms,
+                ..marker
+            })
+            .collect();
+
+        write_sidecar_if_needed(
+            output_path,
+            ClipSidecar {
+                markers,
+                metadata: sidecar.metadata,
+            },
+        )?;
+    }
+
+    Ok(RemuxResult {
+        duration_ms: end_ms - start_ms,
+        bytes_written: file_size(output_path)?,
+        pushed_bytes: 0,
+    })
+}
+
+fn plan_trim(
+    index: &ClipIndex,
+    start_ms: u64,
+    end_ms: u64,
+    mode: TrimMode,
+) -> Result<TrimPlan, String> {
+    let origin_ns = index.origin_ns.ok_or("clip has no video frames")?;
+    let start_ns = origin_ns + start_ms * 1_000_000;
+    let end_ns = (origin_ns + end_ms * 1_000_000).min(index.end_ns);
+
+    if start_ns >= end_ns {
+        return Err("trim start is past the end of the clip".to_string());
+    }
+
+    let keyframe_ns = index
+        .keyframes
+        .iter()
+        .copied()
+        .rev()
+        .find(|ts| *ts <= start_ns)
+        .or(index.keyframes.first().copied())
+        .ok_or("clip has no keyframes")?;
+
+    let next_keyframe_ns = index
+        .keyframes
+        .iter()
+        .copied()
+        .find(|ts| *ts > start_ns)
+        .unwrap_or(end_ns)
+        .min(end_ns);
+
+    // Nothing to re-encode when the range already starts on a keyframe.
+    if mode == TrimMode::Keyframe || keyframe_ns >= start_ns {
+        return Ok(TrimPlan {
+            keyframe_ns,
+            start_ns: keyframe_ns,
+            end_ns,
+            copy_from_ns: None,
+        });
+    }
+
+    Ok(TrimPlan {
+        keyframe_ns,
+        start_ns,
+        end_ns,
+        copy_from_ns: Some(next_keyframe_ns),
+    })
+}
+
+// queue -> h264parse, copying [keyframe, end).
+fn build_copied_video(
+    pipeline: &gst::Pipeline,
+    plan: &TrimPlan,
+) -> Result<(gst::Element, gst::Element), String> {
+    let queue = gst_utils::make("queue")?;
+    let h264parse = gst_utils::make("h264parse")?;
+
+    pipeline
+        .add_many([&queue, &h264parse])
+        .map_err(gst_utils::err)?;
+    queue.link(&h264parse).map_err(gst_utils::err)?;
+
+    attach_range_probe(
+        &pad(&queue, "sink")?,
+        plan.keyframe_ns,
+        plan.end_ns,
+        Some(plan.keyframe_ns),
+    );
+
+    Ok((queue, h264parse))
+}
+
+// tee ─ queue [keyframe, copy_from) -> decodebin -> drop < start -> encoder ─ concat -> h264parse
+//     └ queue [copy_from, end) ─────────────────────────────────────────────┘
+fn build_frame_accurate_video(
+    pipeline: &gst::Pipeline,
+    plan: &TrimPlan,
+    copy_from_ns: u64,
+    format: ClipFormat,
+    encoder: &VideoEncoder,
+) -> Result<(gst::Element, gst::Element), String> {
+    let tee = gst_utils::make("tee")?;
+    let gop_queue = gst_utils::make("queue")?;
+    let decodebin = gst_utils::make("decodebin")?;
+    let decoded_queue = gst_utils::make("queue")?;
+    let copy_queue = gst_utils::make("queue")?;
+    let concat = gst_utils::make("concat")?;
+    let h264parse = gst_utils::make("h264parse")?;
+
+    // concat holds the copied branch back until the re-encoded GOP is done.
+    copy_queue.set_property("max-size-buffers", 0u32);
+    copy_queue.set_property("max-size-bytes", 0u32);
+    copy_queue.set_property("max-size-time", 0u64);
+
+    pipeline
+        .add_many([
+            &tee,
+            &gop_queue,
+            &decodebin,
+            &decoded_queue,
+            &copy_queue,
+            &concat,
+            &h264parse,
+        ])
+        .map_err(gst_utils::err)?;
+
+    for queue in [&gop_queue, &copy_queue] {
+        tee.request_pad_simple("src_%u")
+            .ok_or("failed to request tee pad")?
+            .link(&pad(queue, "sink")?)
+            .map_err(gst_utils::err)?;
+    }
+
+    gop_queue.link(&decodebin).map_err(gst_utils::err)?;
+
+    let decoded_sink = pad(&decoded_queue, "sink")?;
+    let decoded_sink_clone = decoded_sink.clone();
+    decodebin.connect_pad_added(move |_, src_pad| {
+        if pad_kind(src_pad) == Some("video") {
+            let _ = src_pad.link(&decoded_sink_clone);
+        }
+    });
+
+    let encoded = reencode_video(pipeline, encoder, &decoded_queue)?;
+
+    for branch in [&encoded, &copy_queue] {
+        pad(branch, "src")?
+            .link(
+                &concat
+                    .request_pad_simple("sink_%u")
+                    .ok_or("failed to request concat pad")?,
+            )
+            .map_err(gst_utils::err)?;
+    }
+
+    concat.link(&h264parse).map_err(gst_utils::err)?;
+
+    attach_range_probe(
+        &pad(&gop_queue, "sink")?,
+        plan.keyframe_ns,
+        copy_from_ns,
+        None,
+    );
+    attach_range_probe(&decoded_sink, plan.start_ns, u64::MAX, Some(plan.start_ns));
+    attach_range_probe(
+        &pad(&copy_queue, "sink")?,
+        copy_from_ns,
+        plan.end_ns,
+        Some(copy_from_ns),
+    );
+
+    // The re-encoded GOP carries its own SPS/PPS, so MP4 needs them in-band.
+    let out = match format {
+        ClipFormat::Mp4 | ClipFormat::FragmentedMp4 => {
+            let capsfilter = gst_utils::make("capsfilter")?;
+            let caps = gst::Caps::builder("video/x-h264")
+                .field("stream-format", "avc3")
+                .field("alignment", "au")
+                .build();
+            capsfilter.set_property("caps", &caps);
+
+            pipeline.add(&capsfilter).map_err(gst_utils::err)?;
+            h264parse.link(&capsfilter).map_err(gst_utils::err)?;
+            capsfilter
+        }
+        _ => h264parse,
+    };
+
+    Ok((tee, out))
+}
+
+fn pad(element: &gst::Element, name: &str) -> Result<gst::Pad, String> {
+    element
+        .static_pad(name)
+        .ok_or_else(|| format!("missing {} {} pad", element.name(), name))
+}
+
+// Keeps buffers whose DTS (or PTS) falls in [from_ns, to_ns) and sends EOS
+// downstream at the first buffer past the range. With `rebase_ns`, timestamps
+// are shifted so the range starts at zero and segments are reset to match.
+pub(super) fn attach_range_probe(pad: &gst::Pad, from_ns: u64, to_ns: u64, rebase_ns: Option<u64>) {
+    let ended = AtomicBool::new(false);
+
+    pad.add_probe(
+        gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+        move |pad, info| {
+            if ended.load(Ordering::Relaxed) {
+                // Let the EOS'd pad refuse data so upstream stops reading.
+                return gst::PadProbeReturn::Ok;
+            }
+
+            let is_segment = matches!(
+                info.data,
+                Some(gst::PadProbeData::Event(ref event)) if event.type_() == gst::EventType::Segment
+            );
+
+            if is_segment {
+                if rebase_ns.is_some() {
+                    let segment = gst::FormattedSegment::<gst::ClockTime>::new();
+                    info.data = Some(gst::PadProbeData::Event(gst::event::Segment::new(&segment)));
+                }
+                return gst::PadProbeReturn::Ok;
+            }
+
+            let Some(gst::PadProbeData::Buffer(ref mut buffer)) = info.data else {
+                return gst::PadProbeReturn::Ok;
+            };
+
+            let Some(ts) = buffer.dts_or_pts().map(|ts| ts.nseconds()) else {
+                return gst::PadProbeReturn::Ok;
+            };
+
+            if ts >= to_ns {
+                ended.store(true, Ordering::Relaxed);
+                pad.send_event(gst::event::Eos::new());
+                return gst::PadProbeReturn::Drop;
+            }
+
+            if ts < from_ns {
+                return gst::PadProbeReturn::Drop;
+            }
+
+            if let Some(rebase_ns) = rebase_ns {
+                let buffer = buffer.make_mut();
+                let shift = |ts: gst::ClockTime| {
+                    gst::ClockTime::from_nseconds(ts.nseconds().saturating_sub(rebase_ns))
+                };
+
+                buffer.set_pts(buffer.pts().map(shift));
+                buffer.set_dts(buffer.dts().map(shift));
+            }
+
+            gst::PadProbeReturn::Ok
+        },
+    );
+}
+
+// One pass over the clip with fakesinks, recording video keyframes and extent.
+pub(super) fn index_clip(input_path: &Path) -> Result<ClipIndex, String> {
+    let pipeline = gst::Pipeline::new();
+
+    let filesrc = gst_utils::make("filesrc")?;
+    let parsebin = gst_utils::make("parsebin")?;
+
+    let input = input_path.to_str().ok_or("input path is not valid UTF-8")?;
+    filesrc.set_property("location", input);
+
+    pipeline
+        .add_many([&filesrc, &parsebin])
+        .map_err(gst_utils::err)?;
+    filesrc.link(&parsebin).map_err(gst_utils::err)?;
+
+    let index = Arc::new(Mutex::new(ClipIndex::default()));
+    let index_clone = index.clone();
+    let pipeline_weak = pipeline.downgrade();
+
+    parsebin.connect_pad_added(move |_, src_pad| {
+        let Some(pipeline) = pipeline_weak.upgrade() else {
+            return;
+        };
+        let Ok(fakesink) = gst_utils::make("fakesink") else {
+            return;
+        };
+
+        fakesink.set_property("sync", false);
+
+        if pipeline.add(&fakesink).is_err() || fakesink.sync_state_with_parent().is_err() {
+            return;
+        }
+        let Some(sink) = fakesink.static_pad("sink") else {
+            return;
+        };
+        if src_pad.link(&sink).is_err() || pad_kind(src_pad) != Some("video") {
+            return;
+        }
+
+        let index = index_clone.clone();
+        src_pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data else {
+                return gst::PadProbeReturn::Ok;
+            };
+            let Some(ts) = buffer.dts_or_pts().map(|ts| ts.nseconds()) else {
+                return gst::PadProbeReturn::Ok;
+            };
+
+            if let Ok(mut index) = index.lock() {
+                index.origin_ns.get_or_insert(ts);
+
+                let end = buffer
+                    .pts()
+                    .map(|pts| pts.nseconds())
+                    .unwrap_or(ts)
+                    .saturating_add(buffer.duration().map(|d| d.nseconds()).unwrap_or(0));
+                index.end_ns = index.end_ns.max(end);
+
+                if !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+                    index.keyframes.push(ts);
+                    index
+                        .keyframe_pts
+                        .push(buffer.pts().map(|pts| pts.nseconds()).unwrap_or(ts));
+                }
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+    });
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(gst_utils::err)?;
+
+    wait_for_eos(&pipeline)?;
+
+    let mut index = std::mem::take(&mut *index.lock().map_err(gst_utils::err)?);
+    index.keyframe_pts.sort_unstable();
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S: u64 = 1_000_000_000;
+
+    // Video from 1 s to 7 s with keyframes every 2 s.
+    fn index() -> ClipIndex {
+        ClipIndex {
+            origin_ns: Some(S),
+            end_ns: 7 * S,
+            keyframes: vec![S, 3 * S, 5 * S],
+            keyframe_pts: vec![S, 3 * S, 5 * S],
+        }
+    }
+
+    #[test]
+    fn keyframe_trim_starts_on_the_keyframe_before_start() {
+        let plan = plan_trim(&index(), 2500, 4000, TrimMode::Keyframe).unwrap();
+
+        assert_eq!(plan.keyframe_ns, 3 * S);
+        assert_eq!(plan.start_ns, 3 * S);
+        assert_eq!(plan.end_ns, 5 * S);
+        assert_eq!(plan.copy_from_ns, None);
+    }
+
+    #[test]
+    fn frame_accurate_trim_reencodes_up_to_the_next_keyframe() {
+        let plan = plan_trim(&index(), 2500, 5500, TrimMode::FrameAccurate).unwrap();
+
+        assert_eq!(plan.keyframe_ns, 3 * S);
+        assert_eq!(plan.start_ns, 3 * S + S / 2);
+        assert_eq!(plan.end_ns, 6 * S + S / 2);
+        assert_eq!(plan.copy_from_ns, Some(5 * S));
+    }
+
+    #[test]
+    fn frame_accurate_trim_on_a_keyframe_is_a_plain_copy() {
+        let plan = plan_trim(&index(), 2000, 3000, TrimMode::FrameAccurate).unwrap();
+
+        assert_eq!(plan.start_ns, 3 * S);
+        assert_eq!(plan.copy_from_ns, None);
+    }
+
+    #[test]
+    fn frame_accurate_trim_without_a_later_keyframe_reencodes_to_the_end() {
+        let plan = plan_trim(&index(), 4500, 5500, TrimMode::FrameAccurate).unwrap();
+
+        assert_eq!(plan.keyframe_ns, 5 * S);
+        assert_eq!(plan.copy_from_ns, Some(plan.end_ns));
+    }
+
+    #[test]
+    fn end_is_clamped_to_the_clip() {
+        let plan = plan_trim(&index(), 0, 60_000, TrimMode::Keyframe).unwrap();
+
+        assert_eq!(plan.start_ns, S);
+        assert_eq!(plan.end_ns, 7 * S);
+    }
+
+    #[test]
+    fn start_before_the_first_keyframe_uses_it() {
+        let index = ClipIndex {
+            keyframes: vec![2 * S],
+            ..index()
+        };
+
+        let plan = plan_trim(&index, 0, 3000, TrimMode::Keyframe).unwrap();
+
+        assert_eq!(plan.keyframe_ns, 2 * S);
+        assert_eq!(plan.start_ns, 2 * S);
+    }
+
+    #[test]
+    fn rejects_unusable_ranges_and_clips() {
+        assert!(plan_trim(&index(), 6000, 9000, TrimMode::Keyframe).is_err());
+        assert!(plan_trim(&ClipIndex::default(), 0, 1000, TrimMode::Keyframe).is_err());
+
+        let no_keyframes = ClipIndex {
+            keyframes: Vec::new(),
+            ..index()
+        };
+        assert!(plan_trim(&no_keyframes, 0, 1000, TrimMode::Keyframe).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::{fs, path::Path};
+
+use gstreamer as gst;
+use serde::Serialize;
+
+use super::{probe_streams, trim::index_clip, ClipFormat, RemuxResult};
+use crate::gst_utils;
+
+// Container durations include the last frame and the audio tail, which the
+// packet span behind `RemuxResult::duration_ms` doesn't.
+const DURATION_TOLERANCE_MS: u64 = 500;
+const TS_PACKET_SIZE: u64 = 188;
+
+/// What `verify_clip` found in a saved clip.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClipReport {
+    pub has_video: bool,
+    pub has_audio: bool,
+    /// Container duration, when the demuxer reports one.
+    pub duration_ms: Option<u64>,
+    /// Span of the demuxed video frames.
+    pub video_duration_ms: u64,
+    pub starts_on_keyframe: bool,
+    /// The whole file demuxed to EOS without errors.
+    pub complete: bool,
+    pub bytes_on_disk: u64,
+    /// One line per failed check; empty when the clip is fine.
+    pub problems: Vec<String>,
+}
+
+impl ClipReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Demuxes the clip at `path` end to end and checks it against the save
+/// that produced it: video (and, if `expect_audio`, audio) tracks present,
+/// duration close to `expected.duration_ms`, a keyframe first, and no
+/// truncation. Failed checks go into the report; `Err` means the check
+/// itself couldn't run.
+pub fn verify_clip(
+    path: &Path,
+    expected: &RemuxResult,
+    format: ClipFormat,
+    expect_audio: bool,
+) -> Result<ClipReport, String> {
+    gst::init().map_err(gst_utils::err)?;
+
+    let mut report = ClipReport {
+        bytes_on_disk: fs::metadata(path).map_err(gst_utils::err)?.len(),
+        ..ClipReport::default()
+    };
+
+    if report.bytes_on_disk == 0 {
+        report.problems.push("file is empty".to_string());
+        return Ok(report);
+    }
+
+    match probe_streams(path) {
+        Ok(streams) => {
+            report.has_video = streams.video.is_some();
+            report.has_audio = streams.audio.is_some();
+            report.duration_ms = streams.duration_ms;
+        }
+        Err(err) => {
+            report
+                .problems
+                .push(format!("file could not be opened: {}", err));
+            return Ok(report);
+        }
+    }
+
+    match index_clip(path) {
+        Ok(index) => {
+            report.complete = true;
+
+            if let Some(origin_ns) = index.origin_ns {
+                report.video_duration_ms = index.end_ns.saturating_sub(origin_ns) / 1_000_000;
+                report.starts_on_keyframe = index.keyframes.first() == Some(&origin_ns);
+            }
+        }
+        Err(err) => report
+            .problems
+            .push(format!("file is truncated or corrupt: {}", err)),
+    }
+
+    check_report(&mut report, expected, format, expect_audio);
+
+    Ok(report)
+}
+
+// Judges what `verify_clip` read from the file.
+fn check_report(
+    report: &mut ClipReport,
+    expected: &RemuxResult,
+    format: ClipFormat,
+    expect_audio: bool,
+) {
+    if !report.has_video {
+        report.problems.push("no video track".to_string());
+    }
+    if expect_audio && !report.has_audio {
+        report.problems.push("no audio track".to_string());
+    }
+
+    if report.has_video && report.complete && !report.starts_on_keyframe {
+        report
+            .problems
+            .push("first video frame is not a keyframe".to_string());
+    }
+
+    // Fall back to the demuxed video span for containers without a duration.
+    let duration_ms = report.duration_ms.unwrap_or(report.video_duration_ms);
+
+    if duration_ms.abs_diff(expected.duration_ms) > DURATION_TOLERANCE_MS {
+        report.problems.push(format!(
+            "duration is {} ms, expected {} ms",
+            duration_ms, expected.duration_ms
+        ));
+    }
+
+    // A container that claims more time than its frames cover lost its tail.
+    if report.complete
+        && report.has_video
+        && report.duration_ms.is_some_and(|duration_ms| {
+            duration_ms > report.video_duration_ms + DURATION_TOLERANCE_MS
+        })
+    {
+        report
+            .problems
+            .push("video ends before the container duration".to_string());
+    }
+
+    // TS and fragmented MP4 demux cleanly up to the cut and may report the
+    // duration of what's left, so compare the frames against the save too.
+    if report.complete
+        && report.has_video
+        && report.video_duration_ms + DURATION_TOLERANCE_MS < expected.duration_ms
+    {
+        report.problems.push(format!(
+            "video stops after {} ms of {} ms",
+            report.video_duration_ms, expected.duration_ms
+        ));
+    }
+
+    if expected.pushed_bytes == 0 {
+        return;
+    }
+
+    match format {
+        // TS clips are the buffered packets written as-is.
+        ClipFormat::Ts => {
+            if !report.bytes_on_disk.is_multiple_of(TS_PACKET_SIZE) {
+                report
+                    .problems
+                    .push("file ends in a partial TS packet".to_string());
+            }
+            if report.bytes_on_disk != expected.pushed_bytes {
+                report.problems.push(format!(
+                    "{} bytes on disk, {} bytes written",
+                    report.bytes_on_disk, expected.pushed_bytes
+                ));
+            }
+        }
+        // Muxing only drops the TS and PES headers, a few percent of the input.
+        ClipFormat::Mp4 | ClipFormat::Mkv | ClipFormat::FragmentedMp4 => {
+            if report.bytes_on_disk < expected.pushed_bytes / 2 {
+                report.problems.push(format!(
+                    "{} bytes on disk from {} bytes of packets",
+                    report.bytes_on_disk, expected.pushed_bytes
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 10 s save of 1000 TS packets.
+    fn expected() -> RemuxResult {
+        RemuxResult {
+            duration_ms: 10_000,
+            bytes_written: 188_000,
+            pushed_bytes: 188_000,
+        }
+    }
+
+    fn sound_report() -> ClipReport {
+        ClipReport {
+            has_video: true,
+            has_audio: true,
+            duration_ms: Some(10_100),
+            video_duration_ms: 10_033,
+            starts_on_keyframe: true,
+            complete: true,
+            bytes_on_disk: 188_000,
+            problems: Vec::new(),
+        }
+    }
+
+    fn checked(mut report: ClipReport, expected: &RemuxResult, format: ClipFormat) -> ClipReport {
+        check_report(&mut report, expected, format, true);
+        report
+    }
+
+    #[test]
+    fn a_sound_clip_passes_in_every_container() {
+        for format in [
+            ClipFormat::Mp4,
+            ClipFormat::Mkv,
+            ClipFormat::FragmentedMp4,
+            ClipFormat::Ts,
+        ] {
+            let report = checked(sound_report(), &expected(), format);
+            assert!(report.is_ok(), "{:?}: {:?}", format, report.problems);
+        }
+    }
+
+    #[test]
+    fn missing_tracks_and_keyframe_are_reported() {
+        let report = checked(
+            ClipReport {
+                has_audio: false,
+                starts_on_keyframe: false,
+                ..sound_report()
+            },
+            &expected(),
+            ClipFormat::Mp4,
+        );
+
+        assert_eq!(
+            report.problems,
+            vec!["no audio track", "first video frame is not a keyframe"]
+        );
+    }
+
+    #[test]
+    fn truncated_fragmented_mp4_is_reported() {
+        // The demuxer stops cleanly at the last whole fragment and reports
+        // the duration of what it read.
+        let report = checked(
+            ClipReport {
+                duration_ms: Some(6_000),
+                video_duration_ms: 6_000,
+                bytes_on_disk: 110_000,
+                ..sound_report()
+            },
+            &expected(),
+            ClipFormat::FragmentedMp4,
+        );
+
+        assert_eq!(
+            report.problems,
+            vec![
+                "duration is 6000 ms, expected 10000 ms",
+                "video stops after 6000 ms of 10000 ms",
+            ]
+        );
+    }
+
+    #[test]
+    fn video_short_of_the_save_is_reported_without_a_container_duration() {
+        let report = checked(
+            ClipReport {
+                duration_ms: None,
+                video_duration_ms: 9_000,
+                ..sound_report()
+            },
+            &expected(),
+            ClipFormat::Mkv,
+        );
+
+        assert_eq!(
+            report.problems,
+            vec![
+                "duration is 9000 ms, expected 10000 ms",
+                "video stops after 9000 ms of 10000 ms",
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_ts_is_reported() {
+        let report = checked(
+            ClipReport {
+                bytes_on_disk: 187_900,
+                ..sound_report()
+            },
+            &expected(),
+            ClipFormat::Ts,
+        );
+
+        assert_eq!(
+            report.problems,
+            vec![
+                "file ends in a partial TS packet",
+                "187900 bytes on disk, 188000 bytes written",
+            ]
+        );
+    }
+
+    #[test]
+    fn muxed_clips_may_shrink_but_not_collapse() {
+        let shrunk = checked(
+            ClipReport {
+                bytes_on_disk: 180_000,
+                ..sound_report()
+            },
+            &expected(),
+            ClipFormat::Mp4,
+        );
+        assert!(shrunk.is_ok(), "{:?}", shrunk.problems);
+
+        let collapsed = checked(
+            ClipReport {
+                bytes_on_disk: 4_096,
+                ..sound_report()
+            },
+            &expected(),
+            ClipFormat::Mp4,
+        );
+        assert_eq!(
+            collapsed.problems,
+            vec!["4096 bytes on disk from 188000 bytes of packets"]
+        );
+    }
+
+    #[test]
+    fn outputs_not_built_from_packets_skip_the_byte_checks() {
+        let report = checked(
+            ClipReport {
+                bytes_on_disk: 1_000,
+                ..sound_report()
+            },
+            &RemuxResult {
+                pushed_bytes: 0,
+                ..expected()
+            },
+            ClipFormat::Ts,
+        );
+
+        assert!(report.is_ok(), "{:?}", report.problems);
+    }
+}
@@ -1,75 +1,393 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, sync::Arc};
 
-/// A single encoded media packet with a presentation timestamp.
-/// `pts_ms` must be monotonically increasing.
+use serde::Serialize;
+
+use crate::{
+    logger,
+    spill::{SpillLocation, SpillReader, SpillStore},
+};
+
+/// Which elementary stream a muxed packet belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamId {
+    Video,
+    Audio,
+    /// PSI tables (PAT/PMT) and anything not tied to an elementary stream.
+    Other,
+}
+
+/// A single encoded media packet with its timing and buffer flags.
+/// `dts_ns` must be monotonically increasing; it is the timeline the buffer is ordered on.
+/// The payload is shared, so cloning a packet never copies its bytes.
 #[derive(Debug, Clone)]
 pub struct Packet {
-    pub pts_ms: u64,
-    pub data: Vec<u8>,
+    pub stream_id: StreamId,
+    pub pts_ns: u64,
+    pub dts_ns: u64,
+    pub duration_ns: Option<u64>,
+    /// Random access point (no DELTA_UNIT flag on the source buffer).
+    pub keyframe: bool,
+    pub discont: bool,
+    pub data: Arc<[u8]>,
+}
+
+/// A user-placed bookmark on the buffer timeline.
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub dts_ns: u64,
+    pub label: String,
+}
+
+/// Point-in-time health of the replay buffer.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RingBufferStats {
+    pub packets: usize,
+    pub total_bytes: u64,
+    pub duration_ms: u64,
+    pub average_bitrate_kbps: u64,
+    pub keyframes: usize,
+    pub oldest_pts_ns: Option<u64>,
+    pub newest_pts_ns: Option<u64>,
+    /// Largest DTS step between consecutive packets; spikes point at holes.
+    pub largest_gap_ms: u64,
+    pub evicted_packets: u64,
+    pub evicted_bytes: u64,
+    /// The size cap, not the duration, is what currently limits the window.
+    pub byte_capped: bool,
+}
+
+const NS_PER_MS: u64 = 1_000_000;
+
+// Space left between the end of one capture session and the start of the next.
+const SESSION_GAP_NS: u64 = NS_PER_MS;
+
+// A buffered packet. When spilled, the payload lives on disk and `packet.data` is empty.
+#[derive(Clone)]
+struct Slot {
+    packet: Packet,
+    spilled: Option<SpillLocation>,
+}
+
+impl Slot {
+    fn len(&self) -> u64 {
+        match &self.spilled {
+            Some(location) => location.len(),
+            None => self.packet.data.len() as u64,
+        }
+    }
 }
 
 pub struct RingBuffer {
-    max_duration_ms: u64,
-    packets: VecDeque<Packet>,
+    max_duration_ns: u64,
+    max_bytes: Option<u64>,
+    total_bytes: u64,
+    packets: VecDeque<Slot>,
+    // Keyframe DTS, oldest first.
     keyframes: VecDeque<u64>,
+    markers: VecDeque<Marker>,
+    evicted_packets: u64,
+    evicted_bytes: u64,
+    byte_capped: bool,
+    spill: Option<SpillStore>,
+    // Added to every timestamp of the current capture session so it lands after
+    // history kept from earlier sessions.
+    timeline_offset_ns: u64,
+    discont_pending: bool,
 }
 
 impl RingBuffer {
     pub fn new(max_duration_ms: u64) -> Self {
         Self {
-            max_duration_ms,
+            max_duration_ns: max_duration_ms.saturating_mul(NS_PER_MS),
+            max_bytes: None,
+            total_bytes: 0,
             packets: VecDeque::new(),
             keyframes: VecDeque::new(),
+            markers: VecDeque::new(),
+            evicted_packets: 0,
+            evicted_bytes: 0,
+            byte_capped: false,
+            spill: None,
+            timeline_offset_ns: 0,
+            discont_pending: false,
+        }
+    }
+
+    // Moves packet payloads to disk so long windows don't have to fit in RAM.
+    // Switching backends clears the buffer.
+    pub fn set_spill_store(&mut self, spill: Option<SpillStore>) {
+        self.clear();
+        self.spill = spill;
+    }
+
+    pub fn is_spilling(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    pub fn set_max_duration_ms(&mut self, max_duration_ms: u64) {
+        self.max_duration_ns = max_duration_ms.saturating_mul(NS_PER_MS);
+        self.evict_old_packets();
+    }
+
+    // Caps the payload bytes held, on top of the duration limit.
+    // `None` bounds the buffer by duration only.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<u64>) {
+        self.max_bytes = max_bytes;
+        self.evict_old_packets();
+    }
+
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    // Keeps the buffered history and starts a new capture session after it.
+    // A fresh pipeline restarts its running time at zero, so the session is shifted
+    // past the newest packet and its first packet is flagged as a discontinuity.
+    pub fn continue_timeline(&mut self) {
+        let Some(newest) = self.packets.back() else {
+            self.timeline_offset_ns = 0;
+            self.discont_pending = false;
+            return;
+        };
+
+        self.timeline_offset_ns = newest.packet.dts_ns + SESSION_GAP_NS;
+        self.discont_pending = true;
+    }
+
+    // Maps a packet from the capture session's clock onto the buffer timeline.
+    // Packets from `GstCapture` go through here before `push`.
+    pub fn place_on_timeline(&mut self, mut packet: Packet) -> Packet {
+        packet.pts_ns += self.timeline_offset_ns;
+        packet.dts_ns += self.timeline_offset_ns;
+
+        if self.discont_pending {
+            packet.discont = true;
+            self.discont_pending = false;
         }
+
+        packet
     }
 
-    pub fn push(&mut self, packet: Packet) {
-        self.packets.push_back(packet);
+    pub fn push(&mut self, mut packet: Packet) {
+        // Payloads that fail to spill stay in memory rather than being lost.
+        let spilled = self
+            .spill
+            .as_mut()
+            .and_then(|spill| spill.append(&packet.data).ok());
+
+        if spilled.is_some() {
+            packet.data = Arc::from([]);
+        }
+
+        let slot = Slot { packet, spilled };
+        self.total_bytes += slot.len();
+        self.packets.push_back(slot);
         self.evict_old_packets();
     }
 
-    pub fn push_keyframe_pts(&mut self, pts_ms: u64) {
+    /// Records a keyframe at `dts_ns`, the running time of the encoded
+    /// frame's DTS. Compared against `Packet::dts_ns` to find clip starts,
+    /// so passing a PTS misaligns them on streams with B-frames.
+    pub fn push_keyframe_dts(&mut self, dts_ns: u64) {
+        let dts_ns = dts_ns + self.timeline_offset_ns;
         if self
             .keyframes
             .back()
-            .map(|last| *last < pts_ms)
+            .map(|last| *last < dts_ns)
             .unwrap_or(true)
         {
-            self.keyframes.push_back(pts_ms);
+            self.keyframes.push_back(dts_ns);
         }
     }
 
+    // Drops a marker at the newest packet. Returns None while the buffer is empty.
+    pub fn add_marker(&mut self, label: impl Into<String>) -> Option<Marker> {
+        let newest = self.packets.back()?;
+        let marker = Marker {
+            dts_ns: newest.packet.dts_ns,
+            label: label.into(),
+        };
+        self.markers.push_back(marker.clone());
+        Some(marker)
+    }
+
+    // Markers that fall within the given packets, e.g. the ones just saved
+    pub fn markers_for(&self, packets: &[Packet]) -> Vec<Marker> {
+        let (Some(first), Some(last)) = (packets.first(), packets.last()) else {
+            return Vec::new();
+        };
+
+        self.markers_between(first.dts_ns, last.dts_ns)
+    }
+
+    // Markers with `first_dts_ns <= dts <= last_dts_ns`, e.g. a `Snapshot`'s range
+    pub fn markers_between(&self, first_dts_ns: u64, last_dts_ns: u64) -> Vec<Marker> {
+        self.markers
+            .iter()
+            .filter(|marker| marker.dts_ns >= first_dts_ns && marker.dts_ns <= last_dts_ns)
+            .cloned()
+            .collect()
+    }
+
     fn evict_old_packets(&mut self) {
         let Some(newest) = self.packets.back() else {
             return;
         };
 
-        let newest_pts = newest.pts_ms;
+        let newest_dts = newest.packet.dts_ns;
 
         while let Some(oldest) = self.packets.front() {
-            if newest_pts.saturating_sub(oldest.pts_ms) > self.max_duration_ms {
-                self.packets.pop_front();
-            } else {
+            let window_ns = newest_dts.saturating_sub(oldest.packet.dts_ns);
+            let over_duration = window_ns > self.max_duration_ns;
+            // Always keep the newest packet, even if it alone exceeds the budget.
+            let over_bytes =
+                self.packets.len() > 1 && self.max_bytes.is_some_and(|max| self.total_bytes > max);
+
+            if !over_duration && !over_bytes {
                 break;
             }
+
+            if over_duration {
+                self.byte_capped = false;
+            } else if !self.byte_capped {
+                self.byte_capped = true;
+                logger::warn(
+                    "buffer",
+                    format!(
+                        "replay window cut to {} s by the {} MiB size cap",
+                        window_ns / 1_000_000_000,
+                        self.max_bytes.unwrap_or(0) / (1024 * 1024)
+                    ),
+                );
+            }
+
+            if let Some(evicted) = self.packets.pop_front() {
+                self.total_bytes -= evicted.len();
+                self.evicted_packets += 1;
+                self.evicted_bytes += evicted.len();
+                if let (Some(spill), Some(location)) = (self.spill.as_mut(), &evicted.spilled) {
+                    spill.release(location);
+                }
+            }
         }
 
         if let Some(oldest) = self.packets.front() {
             while let Some(keyframe) = self.keyframes.front() {
-                if *keyframe < oldest.pts_ms {
+                if *keyframe < oldest.packet.dts_ns {
                     self.keyframes.pop_front();
                 } else {
                     break;
                 }
             }
+            while let Some(marker) = self.markers.front() {
+                if marker.dts_ns < oldest.packet.dts_ns {
+                    self.markers.pop_front();
+                } else {
+                    break;
+                }
+            }
         } else {
             self.keyframes.clear();
+            self.markers.clear();
         }
     }
 
     // Return a snapshot of the current packets in the buffer
     pub fn snapshot(&self) -> Vec<Packet> {
-        self.packets.iter().cloned().collect()
+        self.stream().collect()
+    }
+
+    // Return the packets from the oldest keyframe onwards, leaving the buffer untouched
+    pub fn snapshot_from_keyframe(&self) -> Vec<Packet> {
+        self.stream_from_keyframe().collect()
+    }
+
+    // Return the last `duration_ms` of the buffer, starting on a keyframe
+    pub fn snapshot_last(&self, duration_ms: u64) -> Vec<Packet> {
+        self.snapshot_window(duration_ms, 0)
+    }
+
+    // Return packets between two offsets back from the newest packet,
+    // e.g. (40_000, 10_000) is "from T-40s to T-10s". The start snaps back to the
+    // nearest preceding keyframe. Audio and video share the mux timeline, so cutting
+    // both on DTS keeps them aligned.
+    pub fn snapshot_window(&self, start_ago_ms: u64, end_ago_ms: u64) -> Vec<Packet> {
+        self.stream_window(start_ago_ms, end_ago_ms).collect()
+    }
+
+    // The `stream*` variants below copy only the slot index; spilled payloads
+    // are read from disk as the returned `Snapshot` is iterated, after the lock
+    // on the buffer has been released. Prefer these for long windows.
+
+    pub fn stream(&self) -> Snapshot {
+        self.stream_slots(self.packets.iter())
+    }
+
+    pub fn stream_from_keyframe(&self) -> Snapshot {
+        let start = self.keyframe_start();
+        self.stream_slots(
+            self.packets
+                .iter()
+                .filter(|slot| start.is_none_or(|start| slot.packet.dts_ns >= start)),
+        )
+    }
+
+    pub fn stream_last(&self, duration_ms: u64) -> Snapshot {
+        self.stream_window(duration_ms, 0)
+    }
+
+    pub fn stream_window(&self, start_ago_ms: u64, end_ago_ms: u64) -> Snapshot {
+        let Some(newest) = self.packets.back() else {
+            return self.stream_slots(std::iter::empty());
+        };
+
+        let newest_dts = newest.packet.dts_ns;
+        let requested_start = newest_dts.saturating_sub(start_ago_ms.saturating_mul(NS_PER_MS));
+        let end = newest_dts.saturating_sub(end_ago_ms.saturating_mul(NS_PER_MS));
+
+        if requested_start > end {
+            return self.stream_slots(std::iter::empty());
+        }
+
+        // Prefer the keyframe before the window; otherwise the first one inside it.
+        let start = self
+            .keyframes
+            .iter()
+            .rev()
+            .find(|keyframe| **keyframe <= requested_start)
+            .or_else(|| self.keyframes.iter().find(|keyframe| **keyframe <= end))
+            .copied()
+            .unwrap_or(requested_start);
+
+        self.stream_slots(
+            self.packets
+                .iter()
+                .filter(|slot| slot.packet.dts_ns >= start && slot.packet.dts_ns <= end),
+        )
+    }
+
+    // Oldest keyframe that still has packets at or after it
+    fn keyframe_start(&self) -> Option<u64> {
+        let start = *self.keyframes.front()?;
+        let newest = self.packets.back()?;
+
+        (newest.packet.dts_ns >= start).then_some(start)
+    }
+
+    fn stream_slots<'a>(&self, slots: impl Iterator<Item = &'a Slot>) -> Snapshot {
+        let slots: Vec<Slot> = slots.cloned().collect();
+        // Only open segment files when something actually has to be read back.
+        let reader = self
+            .spill
+            .as_ref()
+            .filter(|_| slots.iter().any(|slot| slot.spilled.is_some()))
+            .map(|spill| spill.reader());
+
+        Snapshot {
+            slots: slots.into_iter(),
+            reader,
+            lost_packet: false,
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -80,38 +398,118 @@ impl RingBuffer {
         self.packets.is_empty()
     }
 
+    pub fn bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub fn stats(&self) -> RingBufferStats {
+        let duration_ms = self.duration_ms();
+        let largest_gap_ns = self
+            .packets
+            .iter()
+            .zip(self.packets.iter().skip(1))
+            .map(|(prev, next)| next.packet.dts_ns.saturating_sub(prev.packet.dts_ns))
+            .max()
+            .unwrap_or(0);
+
+        RingBufferStats {
+            packets: self.packets.len(),
+            total_bytes: self.total_bytes,
+            duration_ms,
+            // bits per ms is kbit/s
+            average_bitrate_kbps: (self.total_bytes * 8).checked_div(duration_ms).unwrap_or(0),
+            keyframes: self.keyframes.len(),
+            oldest_pts_ns: self.packets.front().map(|slot| slot.packet.pts_ns),
+            newest_pts_ns: self.packets.back().map(|slot| slot.packet.pts_ns),
+            largest_gap_ms: largest_gap_ns / NS_PER_MS,
+            evicted_packets: self.evicted_packets,
+            evicted_bytes: self.evicted_bytes,
+            byte_capped: self.byte_capped,
+        }
+    }
+
     pub fn duration_ms(&self) -> u64 {
         match (self.packets.front(), self.packets.back()) {
-            (Some(first), Some(last)) => last.pts_ms.saturating_sub(first.pts_ms),
+            (Some(first), Some(last)) => {
+                last.packet.dts_ns.saturating_sub(first.packet.dts_ns) / NS_PER_MS
+            }
             _ => 0,
         }
     }
 
     pub fn drain_from_keyframe(&mut self) -> Vec<Packet> {
-        let keyframe_start = self.keyframes.front().cloned();
-        let packets: Vec<Packet> = self.packets.iter().cloned().collect();
+        let packets = self.snapshot_from_keyframe();
         self.clear();
-
-        if let Some(start) = keyframe_start {
-            let has_packet_after = packets
-                .last()
-                .map(|packet| packet.pts_ms >= start)
-                .unwrap_or(false);
-
-            if has_packet_after {
-                return packets
-                    .into_iter()
-                    .filter(|packet| packet.pts_ms >= start)
-                    .collect();
-            }
-        }
-
         packets
     }
 
     pub fn clear(&mut self) {
+        self.total_bytes = 0;
+        self.byte_capped = false;
+        self.timeline_offset_ns = 0;
+        self.discont_pending = false;
         self.packets.clear();
         self.keyframes.clear();
+        self.markers.clear();
+        if let Some(spill) = self.spill.as_mut() {
+            spill.clear();
+        }
+    }
+}
+
+/// Packets copied out of a `RingBuffer`. In-memory payloads are shared with
+/// the buffer; spilled ones are read from disk only as the iterator reaches
+/// them, so a long window never has to fit in RAM at once.
+pub struct Snapshot {
+    slots: std::vec::IntoIter<Slot>,
+    reader: Option<SpillReader>,
+    lost_packet: bool,
+}
+
+impl Snapshot {
+    /// DTS of the first and last packet not yet read.
+    pub fn dts_range(&self) -> Option<(u64, u64)> {
+        let slots = self.slots.as_slice();
+        Some((slots.first()?.packet.dts_ns, slots.last()?.packet.dts_ns))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.len() == 0
+    }
+}
+
+impl Iterator for Snapshot {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        for slot in self.slots.by_ref() {
+            let mut packet = slot.packet;
+
+            if let Some(location) = &slot.spilled {
+                match self.reader.as_mut().map(|reader| reader.read(location)) {
+                    Some(Ok(data)) => packet.data = data.into(),
+                    _ => {
+                        self.lost_packet = true;
+                        continue;
+                    }
+                }
+            }
+
+            // A payload that could not be read back leaves a hole in the TS stream.
+            if self.lost_packet {
+                packet.discont = true;
+                self.lost_packet = false;
+            }
+
+            return Some(packet);
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Unreadable payloads are skipped, so the count is only an upper bound.
+        (0, Some(self.slots.len()))
     }
 }
 
@@ -121,8 +519,13 @@ mod tests {
 
     fn packet(pts_ms: u64) -> Packet {
         Packet {
-            pts_ms,
-            data: vec![0; 10],
+            stream_id: StreamId::Video,
+            pts_ns: pts_ms * NS_PER_MS,
+            dts_ns: pts_ms * NS_PER_MS,
+            duration_ns: None,
+            keyframe: false,
+            discont: false,
+            data: vec![0; 10].into(),
         }
     }
 
@@ -139,6 +542,22 @@ mod tests {
         assert_eq!(buffer.duration_ms(), 3000);
     }
 
+    #[test]
+    fn drain_starts_at_first_keyframe() {
+        let mut buffer = RingBuffer::new(5000);
+
+        buffer.push(packet(0));
+        buffer.push_keyframe_dts(1000 * NS_PER_MS);
+        buffer.push(packet(1000));
+        buffer.push(packet(2000));
+
+        let packets = buffer.drain_from_keyframe();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].dts_ns, 1000 * NS_PER_MS);
+        assert!(buffer.is_empty());
+    }
+
     #[test]
     fn evicts_packets_outside_duration() {
         let mut buffer = RingBuffer::new(2000);
@@ -150,7 +569,177 @@ mod tests {
 
         // 3000 - 0 = 3000 > 2000 → evict
         assert_eq!(buffer.len(), 3);
-        assert_eq!(buffer.snapshot()[0].pts_ms, 1000);
+        assert_eq!(buffer.snapshot()[0].pts_ns, 1000 * NS_PER_MS);
+    }
+
+    #[test]
+    fn evicts_oldest_packets_over_byte_budget() {
+        let mut buffer = RingBuffer::new(60_000);
+        buffer.set_max_bytes(Some(25));
+
+        buffer.push(packet(0));
+        buffer.push(packet(1000));
+        assert_eq!(buffer.bytes(), 20);
+
+        buffer.push(packet(2000));
+
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.bytes(), 20);
+        assert_eq!(buffer.snapshot()[0].pts_ns, 1000 * NS_PER_MS);
+        assert!(buffer.stats().byte_capped);
+    }
+
+    #[test]
+    fn spilled_packets_round_trip_through_disk() {
+        let dir = std::env::temp_dir().join(format!("clip-spill-test-{}", std::process::id()));
+        let mut buffer = RingBuffer::new(2000);
+        buffer.set_spill_store(Some(SpillStore::open(&dir, 32).unwrap()));
+
+        for (i, pts_ms) in [0, 1000, 2000, 3000].into_iter().enumerate() {
+            let mut p = packet(pts_ms);
+            p.data = vec![i as u8; 10].into();
+            buffer.push(p);
+        }
+
+        let snapshot = buffer.snapshot();
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.bytes(), 30);
+        assert_eq!(&snapshot[0].data[..], &[1; 10]);
+        assert_eq!(&snapshot[2].data[..], &[3; 10]);
+
+        buffer.set_spill_store(None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn streamed_snapshot_reads_spilled_packets_evicted_meanwhile() {
+        let dir = std::env::temp_dir().join(format!("clip-stream-test-{}", std::process::id()));
+        let mut buffer = RingBuffer::new(2000);
+        buffer.set_spill_store(Some(SpillStore::open(&dir, 20).unwrap()));
+
+        for (i, pts_ms) in [0, 1000, 2000].into_iter().enumerate() {
+            let mut p = packet(pts_ms);
+            p.data = vec![i as u8; 10].into();
+            buffer.push(p);
+        }
+
+        let stream = buffer.stream();
+        assert_eq!(stream.dts_range(), Some((0, 2000 * NS_PER_MS)));
+
+        // Evicts the first two packets and deletes their segment.
+        for pts_ms in [4000, 5000] {
+            buffer.push(packet(pts_ms));
+        }
+
+        let packets: Vec<Packet> = stream.collect();
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(&packets[0].data[..], &[0; 10]);
+        assert_eq!(&packets[2].data[..], &[2; 10]);
+
+        buffer.set_spill_store(None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn snapshot_from_keyframe_keeps_buffer_intact() {
+        let mut buffer = RingBuffer::new(5000);
+
+        buffer.push(packet(0));
+        buffer.push_keyframe_dts(1000 * NS_PER_MS);
+        buffer.push(packet(1000));
+        buffer.push(packet(2000));
+
+        let first = buffer.snapshot_from_keyframe();
+        let second = buffer.snapshot_from_keyframe();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 2);
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn window_snaps_back_to_preceding_keyframe() {
+        let mut buffer = RingBuffer::new(60_000);
+
+        for pts_ms in (0..=10_000).step_by(1000) {
+            if pts_ms % 4000 == 0 {
+                buffer.push_keyframe_dts(pts_ms * NS_PER_MS);
+            }
+            buffer.push(packet(pts_ms));
+        }
+
+        // T-5s is 5000ms, which snaps back to the keyframe at 4000ms.
+        let last = buffer.snapshot_last(5000);
+        assert_eq!(last.first().unwrap().dts_ns, 4000 * NS_PER_MS);
+        assert_eq!(last.last().unwrap().dts_ns, 10_000 * NS_PER_MS);
+
+        // T-9s..T-3s is 1000..7000ms, which snaps back to 0ms.
+        let window = buffer.snapshot_window(9000, 3000);
+        assert_eq!(window.first().unwrap().dts_ns, 0);
+        assert_eq!(window.last().unwrap().dts_ns, 7000 * NS_PER_MS);
+        assert_eq!(buffer.len(), 11);
+    }
+
+    #[test]
+    fn markers_are_evicted_with_packets() {
+        let mut buffer = RingBuffer::new(2000);
+
+        buffer.push(packet(0));
+        buffer.add_marker("early");
+        buffer.push(packet(1000));
+        buffer.add_marker("late");
+
+        assert_eq!(buffer.markers_for(&buffer.snapshot()).len(), 2);
+
+        buffer.push(packet(3000));
+
+        let markers = buffer.markers_for(&buffer.snapshot());
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].label, "late");
+    }
+
+    #[test]
+    fn stats_report_gaps_bitrate_and_evictions() {
+        let mut buffer = RingBuffer::new(4000);
+
+        buffer.push(packet(0));
+        buffer.push(packet(1000));
+        buffer.push(packet(4000));
+        buffer.push(packet(5000));
+
+        let stats = buffer.stats();
+
+        assert_eq!(stats.packets, 3);
+        assert_eq!(stats.total_bytes, 30);
+        assert_eq!(stats.duration_ms, 4000);
+        assert_eq!(stats.largest_gap_ms, 3000);
+        assert_eq!(stats.evicted_packets, 1);
+        assert_eq!(stats.evicted_bytes, 10);
+    }
+
+    #[test]
+    fn continued_timeline_follows_kept_history() {
+        let mut buffer = RingBuffer::new(60_000);
+
+        buffer.push(packet(0));
+        buffer.push(packet(5000));
+
+        buffer.continue_timeline();
+        buffer.push_keyframe_dts(0);
+        let joined = buffer.place_on_timeline(packet(0));
+        buffer.push(joined);
+        let next = buffer.place_on_timeline(packet(1000));
+        buffer.push(next);
+
+        let snapshot = buffer.snapshot();
+
+        assert_eq!(snapshot.len(), 4);
+        assert!(snapshot[2].discont);
+        assert!(!snapshot[3].discont);
+        assert_eq!(snapshot[2].dts_ns, 5000 * NS_PER_MS + SESSION_GAP_NS);
+        assert_eq!(buffer.snapshot_from_keyframe().len(), 2);
     }
 
     #[test]
@@ -163,8 +752,8 @@ mod tests {
 
         let snapshot = buffer.snapshot();
 
-        assert_eq!(snapshot[0].pts_ms, 10);
-        assert_eq!(snapshot[1].pts_ms, 20);
-        assert_eq!(snapshot[2].pts_ms, 30);
+        assert_eq!(snapshot[0].pts_ns, 10 * NS_PER_MS);
+        assert_eq!(snapshot[1].pts_ns, 20 * NS_PER_MS);
+        assert_eq!(snapshot[2].pts_ns, 30 * NS_PER_MS);
     }
 }
@@ -9,8 +9,22 @@ use directories::ProjectDirs;
 use crate::{
     capture_devices::{AudioDevice, VideoDevice, VideoDeviceKind},
     encoders::VideoEncoderDescriptor,
+    remux::ClipFormat,
 };
 
+/// Longest post-roll a clip save may hold open. Tapped packets are queued in
+/// memory until the save reaches them.
+pub const MAX_POST_ROLL_SECS: u32 = 120;
+
+/// Where the replay buffer keeps packet payloads.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BufferBackend {
+    #[default]
+    Memory,
+    Disk,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserSettings {
     pub video_device_id: String,
@@ -26,15 +40,37 @@ pub struct UserSettings {
     pub bitrate_kbps: u32,
     #[serde(default = "default_clips_dir")]
     pub clips_dir: String,
+    #[serde(default = "default_buffer_seconds")]
+    pub buffer_seconds: u32,
+    /// Size cap for the in-memory replay buffer in MiB. `None` bounds it by
+    /// duration only.
+    #[serde(default = "default_buffer_max_mb")]
+    pub buffer_max_mb: Option<u32>,
+    /// Size cap for the replay buffer in MiB when it spills to disk. `None`
+    /// bounds it by duration only.
+    #[serde(default = "default_buffer_disk_max_mb")]
+    pub buffer_disk_max_mb: Option<u32>,
+    #[serde(default)]
+    pub buffer_backend: BufferBackend,
+    /// Seconds to keep recording after a clip is requested. 0 saves immediately.
+    #[serde(default)]
+    pub post_roll_secs: u32,
+    #[serde(default)]
+    pub clip_format: ClipFormat,
 }
 
 pub fn settings_path() -> io::Result<PathBuf> {
-    let project = ProjectDirs::from("com", "clip", "clip").ok_or_else(|| {
-        io::Error::new(io::ErrorKind::Other, "failed to resolve config directory")
-    })?;
+    let project = ProjectDirs::from("com", "clip", "clip")
+        .ok_or_else(|| io::Error::other("failed to resolve config directory"))?;
     Ok(project.config_dir().join("settings.json"))
 }
 
+pub fn spill_dir() -> io::Result<PathBuf> {
+    let project = ProjectDirs::from("com", "clip", "clip")
+        .ok_or_else(|| io::Error::other("failed to resolve cache directory"))?;
+    Ok(project.cache_dir().join("replay"))
+}
+
 pub fn load_settings() -> io::Result<Option<UserSettings>> {
     let path = settings_path()?;
     if !path.exists() {
@@ -51,8 +87,7 @@ pub fn save_settings(settings: &UserSettings) -> io::Result<()> {
     let path = settings_path()?;
     ensure_parent_dir(&path)?;
 
-    let data = serde_json::to_string_pretty(settings)
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let data = serde_json::to_string_pretty(settings).map_err(io::Error::other)?;
     fs::write(path, data)?;
     Ok(())
 }
@@ -62,9 +97,9 @@ pub fn default_settings(
     encoders: &[VideoEncoderDescriptor],
 ) -> io::Result<UserSettings> {
     let default_video = prefer_screen_device(video_devices)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no video devices available"))?;
+        .ok_or_else(|| io::Error::other("no video devices available"))?;
     let default_encoder = prefer_hardware_encoder(encoders)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no video encoders available"))?;
+        .ok_or_else(|| io::Error::other("no video encoders available"))?;
 
     Ok(UserSettings {
         video_device_id: default_video.id.clone(),
@@ -76,6 +111,12 @@ pub fn default_settings(
         framerate: 60,
         bitrate_kbps: 20_000,
         clips_dir: default_clips_dir(),
+        buffer_seconds: default_buffer_seconds(),
+        buffer_max_mb: default_buffer_max_mb(),
+        buffer_disk_max_mb: default_buffer_disk_max_mb(),
+        buffer_backend: BufferBackend::default(),
+        post_roll_secs: 0,
+        clip_format: ClipFormat::default(),
     })
 }
 
@@ -139,6 +180,29 @@ pub fn apply_startup_fallbacks(
         changes.push("clips directory reset to default".to_string());
     }
 
+    if settings.buffer_seconds == 0 {
+        settings.buffer_seconds = default_buffer_seconds();
+        changes.push("buffer length reset to 30 seconds".to_string());
+    }
+
+    if settings.buffer_max_mb == Some(0) {
+        settings.buffer_max_mb = default_buffer_max_mb();
+        changes.push("buffer memory cap reset to default".to_string());
+    }
+
+    if settings.buffer_disk_max_mb == Some(0) {
+        settings.buffer_disk_max_mb = default_buffer_disk_max_mb();
+        changes.push("buffer disk cap reset to default".to_string());
+    }
+
+    if settings.post_roll_secs > MAX_POST_ROLL_SECS {
+        settings.post_roll_secs = MAX_POST_ROLL_SECS;
+        changes.push(format!(
+            "post-roll clamped to {} seconds",
+            MAX_POST_ROLL_SECS
+        ));
+    }
+
     (settings, changes)
 }
 
@@ -189,6 +253,25 @@ pub fn validate_settings(
         return Err("clips directory must not be empty".to_string());
     }
 
+    if settings.buffer_seconds == 0 {
+        return Err("buffer length must be greater than zero".to_string());
+    }
+
+    if settings.buffer_max_mb == Some(0) {
+        return Err("buffer memory cap must be greater than zero".to_string());
+    }
+
+    if settings.buffer_disk_max_mb == Some(0) {
+        return Err("buffer disk cap must be greater than zero".to_string());
+    }
+
+    if settings.post_roll_secs > MAX_POST_ROLL_SECS {
+        return Err(format!(
+            "post-roll must be at most {} seconds",
+            MAX_POST_ROLL_SECS
+        ));
+    }
+
     Ok(())
 }
 
@@ -199,16 +282,14 @@ fn ensure_parent_dir(path: &Path) -> io::Result<()> {
     Ok(())
 }
 
-fn prefer_hardware_encoder<'a>(
-    encoders: &'a [VideoEncoderDescriptor],
-) -> Option<&'a VideoEncoderDescriptor> {
+fn prefer_hardware_encoder(encoders: &[VideoEncoderDescriptor]) -> Option<&VideoEncoderDescriptor> {
     encoders
         .iter()
         .find(|enc| enc.is_hardware)
         .or_else(|| encoders.first())
 }
 
-fn prefer_screen_device<'a>(devices: &'a [VideoDevice]) -> Option<&'a VideoDevice> {
+fn prefer_screen_device(devices: &[VideoDevice]) -> Option<&VideoDevice> {
     devices
         .iter()
         .find(|device| matches!(device.kind, VideoDeviceKind::Screen))
@@ -230,3 +311,27 @@ fn default_mic_volume() -> f32 {
 fn default_clips_dir() -> String {
     "clips".to_string()
 }
+
+fn default_buffer_seconds() -> u32 {
+    30
+}
+
+fn default_buffer_max_mb() -> Option<u32> {
+    Some(1024)
+}
+
+// 30 minutes at 20 Mbps is about 4.5 GB.
+fn default_buffer_disk_max_mb() -> Option<u32> {
+    Some(16 * 1024)
+}
+
+/// Byte limit for `RingBuffer`: `buffer_max_mb` for the memory backend,
+/// `buffer_disk_max_mb` when spilling to disk.
+pub fn buffer_max_bytes(settings: &UserSettings) -> Option<u64> {
+    let max_mb = match settings.buffer_backend {
+        BufferBackend::Memory => settings.buffer_max_mb,
+        BufferBackend::Disk => settings.buffer_disk_max_mb,
+    };
+
+    max_mb.map(|mb| u64::from(mb) * 1024 * 1024)
+}
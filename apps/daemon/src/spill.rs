@@ -0,0 +1,248 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_EXTENSION: &str = "ts";
+// Packets are appended under the ring buffer lock, so batch them into fewer writes.
+const WRITE_BUFFER_BYTES: usize = 256 * 1024;
+
+/// Where a spilled packet payload lives on disk.
+#[derive(Debug, Clone, Copy)]
+pub struct SpillLocation {
+    segment: u64,
+    offset: u64,
+    len: u64,
+}
+
+impl SpillLocation {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+struct Segment {
+    id: u64,
+    bytes: u64,
+    live_packets: usize,
+}
+
+/// Disk backend for the replay buffer:
+/// - Appends packet payloads to rotating segment files in a cache directory.
+/// - Segments are raw packet payloads cut at byte boundaries, not standalone TS files.
+/// - A segment is deleted once every packet in it has been released.
+/// - Does NOT index packets; `RingBuffer` keeps timestamps and keyframes in memory.
+pub struct SpillStore {
+    dir: PathBuf,
+    segment_bytes: u64,
+    segments: VecDeque<Segment>,
+    // Behind a lock so `reader` can flush it through a shared reference.
+    writer: Mutex<Option<BufWriter<File>>>,
+    next_id: u64,
+}
+
+impl SpillStore {
+    pub fn open(dir: &Path, segment_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        remove_stale_segments(dir)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segment_bytes: segment_bytes.max(1),
+            segments: VecDeque::new(),
+            writer: Mutex::new(None),
+            next_id: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> io::Result<SpillLocation> {
+        let needs_rotation = self
+            .segments
+            .back()
+            .map(|segment| segment.bytes >= self.segment_bytes)
+            .unwrap_or(true);
+
+        if needs_rotation || self.writer_mut().is_none() {
+            self.rotate()?;
+        }
+
+        let writer = self
+            .writer
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let (Some(writer), Some(segment)) = (writer.as_mut(), self.segments.back_mut()) else {
+            return Err(io::Error::other("no open spill segment"));
+        };
+
+        writer.write_all(data)?;
+
+        let location = SpillLocation {
+            segment: segment.id,
+            offset: segment.bytes,
+            len: data.len() as u64,
+        };
+
+        segment.bytes += location.len;
+        segment.live_packets += 1;
+
+        Ok(location)
+    }
+
+    /// Marks a packet as evicted and deletes segments that no longer hold live packets.
+    pub fn release(&mut self, location: &SpillLocation) {
+        if let Some(segment) = self
+            .segments
+            .iter_mut()
+            .find(|segment| segment.id == location.segment)
+        {
+            segment.live_packets = segment.live_packets.saturating_sub(1);
+        }
+
+        self.prune_released();
+    }
+
+    /// Opens every live segment for reading. The files are opened up front, so
+    /// the reader needs no access to the store afterwards, and packets evicted
+    /// while it is in use stay readable until it is dropped.
+    pub fn reader(&self) -> SpillReader {
+        // Buffered payloads must reach the file before it is read.
+        if let Some(writer) = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_mut()
+        {
+            let _ = writer.flush();
+        }
+
+        let files = self
+            .segments
+            .iter()
+            .filter_map(|segment| {
+                File::open(self.segment_path(segment.id))
+                    .ok()
+                    .map(|file| (segment.id, file))
+            })
+            .collect();
+
+        SpillReader { files }
+    }
+
+    pub fn clear(&mut self) {
+        *self.writer_mut() = None;
+
+        while let Some(segment) = self.segments.pop_front() {
+            let _ = fs::remove_file(self.segment_path(segment.id));
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let id = self.next_id;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(self.segment_path(id))?;
+
+        self.next_id += 1;
+        // Replacing the writer flushes the previous segment.
+        *self.writer_mut() = Some(BufWriter::with_capacity(WRITE_BUFFER_BYTES, file));
+        self.segments.push_back(Segment {
+            id,
+            bytes: 0,
+            live_packets: 0,
+        });
+
+        // The previous segment may already be fully released.
+        self.prune_released();
+
+        Ok(())
+    }
+
+    // Deletes fully released segments from the front, never the one being written.
+    fn prune_released(&mut self) {
+        while self.segments.len() > 1 {
+            match self.segments.front() {
+                Some(front) if front.live_packets == 0 => {
+                    let id = front.id;
+                    self.segments.pop_front();
+                    let _ = fs::remove_file(self.segment_path(id));
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn writer_mut(&mut self) -> &mut Option<BufWriter<File>> {
+        self.writer
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn segment_path(&self, id: u64) -> PathBuf {
+        self.dir
+            .join(format!("{}{:08}.{}", SEGMENT_PREFIX, id, SEGMENT_EXTENSION))
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Reads spilled payloads back from the segments that were live when it was
+/// created, see `SpillStore::reader`.
+pub struct SpillReader {
+    // (segment id, file), in segment order.
+    files: Vec<(u64, File)>,
+}
+
+impl SpillReader {
+    pub fn read(&mut self, location: &SpillLocation) -> io::Result<Vec<u8>> {
+        let index = self
+            .files
+            .binary_search_by_key(&location.segment, |(id, _)| *id)
+            .map_err(|_| io::Error::other("spill segment is gone"))?;
+        let file = &mut self.files[index].1;
+
+        let mut data = vec![0; location.len as usize];
+        file.seek(SeekFrom::Start(location.offset))?;
+        file.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+}
+
+fn remove_stale_segments(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let is_segment = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| {
+                name.starts_with(SEGMENT_PREFIX)
+                    && name.ends_with(&format!(".{}", SEGMENT_EXTENSION))
+            })
+            .unwrap_or(false);
+
+        if is_segment {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    Ok(())
+}
@@ -21,15 +21,22 @@ impl VideoEncoder {
         })
     }
 
+    pub fn new(encoder_id: &str, framerate: u32, bitrate_kbps: u32) -> Self {
+        Self {
+            encoder_id: encoder_id.to_string(),
+            framerate,
+            bitrate_kbps,
+        }
+    }
+
+    pub fn encoder_id(&self) -> &str {
+        &self.encoder_id
+    }
+
     pub fn build(&self, pipeline: &gst::Pipeline, input: GraphOutput) -> io::Result<GraphOutput> {
         let enc = gst::ElementFactory::make(&self.encoder_id)
             .build()
-            .map_err(|_| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("missing encoder {}", self.encoder_id),
-                )
-            })?;
+            .map_err(|_| io::Error::other(format!("missing encoder {}", self.encoder_id)))?;
 
         let gop = self.framerate.max(1);
 
@@ -40,7 +47,7 @@ impl VideoEncoder {
         set_bool(&enc, "insert-sps-pps", true);
 
         let h264parse = make("h264parse")?;
-        h264parse.set_property("config-interval", &1i32);
+        h264parse.set_property("config-interval", 1i32);
 
         let capsfilter = make("capsfilter")?;
         let caps = gst::Caps::builder("video/x-h264")
@@ -51,8 +58,8 @@ impl VideoEncoder {
         capsfilter.set_property("caps", &caps);
 
         pipeline
-            .add_many(&[&enc, &h264parse, &capsfilter])
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            .add_many([&enc, &h264parse, &capsfilter])
+            .map_err(|e| io::Error::other(e.to_string()))?;
 
         input.element.link_io(&enc)?;
         enc.link_io(&h264parse)?;
@@ -67,23 +74,23 @@ impl VideoEncoder {
 fn make(name: &str) -> io::Result<gst::Element> {
     gst::ElementFactory::make(name)
         .build()
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, format!("missing element {}", name)))
+        .map_err(|_| io::Error::other(format!("missing element {}", name)))
 }
 
 fn set_i32(element: &gst::Element, name: &str, value: i32) {
     if element.find_property(name).is_some() {
-        element.set_property(name, &value);
+        element.set_property(name, value);
     }
 }
 
 fn set_u32(element: &gst::Element, name: &str, value: u32) {
     if element.find_property(name).is_some() {
-        element.set_property(name, &value);
+        element.set_property(name, value);
     }
 }
 
 fn set_bool(element: &gst::Element, name: &str, value: bool) {
     if element.find_property(name).is_some() {
-        element.set_property(name, &value);
+        element.set_property(name, value);
     }
 }
@@ -32,21 +32,62 @@ impl VideoGraph {
 
         let queue = gst::ElementFactory::make("queue")
             .build()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to create queue element"))?;
+            .map_err(|_| io::Error::other("failed to create queue element"))?;
         pipeline
             .add(&queue)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to add queue element"))?;
+            .map_err(|_| io::Error::other("failed to add queue element"))?;
         encoded_out
             .element
             .link(&queue)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to link queue element"))?;
+            .map_err(|_| io::Error::other("failed to link queue element"))?;
 
         Ok(Self {
             output: GraphOutput { element: queue },
         })
     }
 
-    pub fn attach_keyframe_tracker(&self, _ring_buffer: Arc<Mutex<RingBuffer>>) -> io::Result<()> {
+    // Records keyframe timestamps into the ring buffer so clips can start on an IDR.
+    // The probe sits after h264parse, so DELTA_UNIT reflects whole access units.
+    // Timestamps are converted to running time, which is what mpegtsmux emits.
+    pub fn attach_keyframe_tracker(&self, ring_buffer: Arc<Mutex<RingBuffer>>) -> io::Result<()> {
+        let src_pad = self
+            .output
+            .element
+            .static_pad("src")
+            .ok_or_else(|| io::Error::other("missing video output src pad"))?;
+
+        src_pad
+            .add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+                let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data else {
+                    return gst::PadProbeReturn::Ok;
+                };
+
+                if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+                    return gst::PadProbeReturn::Ok;
+                }
+
+                let Some(ts) = buffer.dts_or_pts() else {
+                    return gst::PadProbeReturn::Ok;
+                };
+
+                let running_time = pad
+                    .sticky_event::<gst::event::Segment>(0)
+                    .and_then(|event| {
+                        event
+                            .segment()
+                            .downcast_ref::<gst::ClockTime>()
+                            .and_then(|segment| segment.to_running_time(ts))
+                    })
+                    .unwrap_or(ts);
+
+                if let Ok(mut rb) = ring_buffer.lock() {
+                    rb.push_keyframe_dts(running_time.nseconds());
+                }
+
+                gst::PadProbeReturn::Ok
+            })
+            .ok_or_else(|| io::Error::other("failed to attach keyframe probe"))?;
+
         Ok(())
     }
 }
@@ -18,18 +18,13 @@ pub enum VideoSource {
 
 impl VideoSource {
     pub fn from_settings(config: &UserSettings) -> io::Result<Self> {
-        let encoder_info =
-            encoders::find_video_encoder(&config.video_encoder_id)?.ok_or_else(|| {
-                io::Error::new(io::ErrorKind::Other, "selected encoder not available")
-            })?;
+        let encoder_info = encoders::find_video_encoder(&config.video_encoder_id)?
+            .ok_or_else(|| io::Error::other("selected encoder not available"))?;
 
         let requires_d3d11 = encoder_info.required_memory.as_deref() == Some("D3D11Memory");
 
         if encoder_info.required_memory.as_deref() == Some("D3D12Memory") {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "D3D12 encoders are not supported",
-            ));
+            return Err(io::Error::other("D3D12 encoders are not supported"));
         }
 
         Ok(Self::Screen {
@@ -58,13 +53,13 @@ fn build_screen_source(
 ) -> io::Result<GraphOutput> {
     let video_src = gst::ElementFactory::make("d3d11screencapturesrc")
         .build()
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, "missing d3d11screencapturesrc"))?;
+        .map_err(|_| io::Error::other("missing d3d11screencapturesrc"))?;
 
     if let Some(index) = monitor_index_from_id(monitor_id) {
-        video_src.set_property("monitor_index", &index);
+        video_src.set_property("monitor_index", index);
     }
 
-    video_src.set_property("do-timestamp", &true);
+    video_src.set_property("do-timestamp", true);
 
     let d3d11convert = make("d3d11convert")?;
     let capsfilter = make("capsfilter")?;
@@ -90,8 +85,8 @@ fn build_screen_source(
     capsfilter.set_property("caps", &caps);
 
     pipeline
-        .add_many(&[&video_src, &d3d11convert, &capsfilter])
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        .add_many([&video_src, &d3d11convert, &capsfilter])
+        .map_err(|e| io::Error::other(e.to_string()))?;
 
     video_src.link_io(&d3d11convert)?;
     d3d11convert.link_io(&capsfilter)?;
@@ -104,7 +99,7 @@ fn build_screen_source(
 fn make(name: &str) -> io::Result<gst::Element> {
     gst::ElementFactory::make(name)
         .build()
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, format!("missing element {}", name)))
+        .map_err(|_| io::Error::other(format!("missing element {}", name)))
 }
 
 fn monitor_index_from_id(id: &str) -> Option<i32> {
@@ -1,6 +1,7 @@
 use std::{
+    collections::HashMap,
     fs::{self},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
@@ -12,24 +13,37 @@ use clip_service::{
         list_video_devices as list_video_devices_inner, AudioDevice, VideoDevice,
     },
     encoders::{list_video_encoders as list_video_encoders_inner, VideoEncoderDescriptor},
-    gst_capture::GstCapture,
+    gst_capture::{CaptureStats, GstCapture},
     logger,
-    ring_buffer::RingBuffer,
+    post_roll::PostRoll,
+    remux::{
+        find_thumbnail, is_thumbnail, read_sidecar, AnimationExport, AnimationSource, CancelToken,
+        ClipFormat, ClipMetadata, ClipReport, RemuxJob, SizeExport, ThumbnailOptions, TrimMode,
+        CANCELLED,
+    },
+    ring_buffer::{RingBuffer, RingBufferStats},
     settings::{
-        apply_startup_fallbacks, default_settings, load_settings, save_settings, validate_settings,
-        UserSettings,
+        apply_startup_fallbacks, buffer_max_bytes, default_settings, load_settings, save_settings,
+        spill_dir, validate_settings, BufferBackend, UserSettings,
     },
+    spill::SpillStore,
+    video::encoder::VideoEncoder,
 };
 
 use gst::prelude::*;
 use gstreamer as gst;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, Manager, State};
 
 struct CaptureRuntime {
     settings: UserSettings,
     capture: Option<GstCapture>,
     ring_buffer: Arc<Mutex<RingBuffer>>,
+    // Running clip saves and exports by output filename, for `cancel_export`.
+    exports: HashMap<String, CancelToken>,
+    // Label of the device the buffered video came from, looked up when capture
+    // starts so clip saves don't enumerate devices.
+    device_label: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -38,6 +52,9 @@ struct StatusResponse {
     buffering: bool,
     buffer_seconds: u32,
     ring_buffer_packets: usize,
+    ring_buffer_bytes: u64,
+    ring_buffer_max_bytes: Option<u64>,
+    capture_stats: Option<CaptureStats>,
 }
 
 #[derive(Serialize)]
@@ -46,12 +63,67 @@ struct ClipResponse {
     packets: usize,
     duration_ms: u64,
     bytes: usize,
+    verification: Option<ClipReport>,
+}
+
+#[derive(Serialize)]
+struct ExportResponse {
+    filename: String,
+    duration_ms: u64,
+    bytes: u64,
+}
+
+// Frames for `export_animation`, tagged by `source`.
+#[derive(Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+enum AnimationRange {
+    /// `[start_ms, end_ms)` of a saved clip, measured from its first video frame.
+    Clip {
+        filename: String,
+        start_ms: u64,
+        end_ms: u64,
+    },
+    /// The replay buffer from `start_ago_ms` to `end_ago_ms` before the newest
+    /// packet, like `RingBuffer::stream_window`.
+    Buffer { start_ago_ms: u64, end_ago_ms: u64 },
+}
+
+impl AnimationRange {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            AnimationRange::Clip {
+                start_ms, end_ms, ..
+            } if end_ms <= start_ms => Err("animation end must be after its start".to_string()),
+            AnimationRange::Buffer {
+                start_ago_ms,
+                end_ago_ms,
+            } if start_ago_ms <= end_ago_ms => {
+                Err("animation start must be further back than its end".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct ClipInfo {
     filename: String,
     size_bytes: u64,
+    thumbnail: Option<String>,
+    metadata: Option<ClipMetadata>,
+}
+
+#[derive(Serialize)]
+struct ThumbnailResponse {
+    poster: String,
+    contact_sheet: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct ClipProgressEvent {
+    filename: String,
+    stage: String,
+    progress: f32,
 }
 
 #[derive(Clone, Serialize)]
@@ -68,6 +140,15 @@ fn emit_capture_status(app: &AppHandle, status: &str, message: Option<String>) {
     let _ = app.emit("capture-status", payload);
 }
 
+fn emit_clip_progress(app: &AppHandle, filename: &str, stage: &str, progress: f32) {
+    let payload = ClipProgressEvent {
+        filename: filename.to_string(),
+        stage: stage.to_string(),
+        progress,
+    };
+    let _ = app.emit("clip-progress", payload);
+}
+
 fn should_restart_capture(a: &UserSettings, b: &UserSettings) -> bool {
     a.video_device_id != b.video_device_id
         || a.system_audio_enabled != b.system_audio_enabled
@@ -77,6 +158,25 @@ fn should_restart_capture(a: &UserSettings, b: &UserSettings) -> bool {
         || a.bitrate_kbps != b.bitrate_kbps
 }
 
+fn has_audio(settings: &UserSettings) -> bool {
+    settings.system_audio_enabled
+        || settings
+            .mic_device_id
+            .as_ref()
+            .is_some_and(|id| !id.is_empty())
+}
+
+// Whether packets from a pipeline built with `b` can follow packets from `a` in the
+// same clip. The capture device, encoder, framerate and track layout must match;
+// resolution isn't known until the new pipeline negotiates, so the same device
+// stands in for it. Bitrate and which microphone feeds the audio track do not matter.
+fn streams_compatible(a: &UserSettings, b: &UserSettings) -> bool {
+    a.video_device_id == b.video_device_id
+        && a.video_encoder_id == b.video_encoder_id
+        && a.framerate == b.framerate
+        && has_audio(a) == has_audio(b)
+}
+
 fn apply_volume_elements(
     system_volume: Option<gst::Element>,
     mic_volume: Option<gst::Element>,
@@ -84,17 +184,56 @@ fn apply_volume_elements(
 ) {
     if let Some(element) = system_volume {
         let value = settings.system_audio_volume as f64;
-        element.set_property("volume", &value);
+        element.set_property("volume", value);
     }
     if let Some(element) = mic_volume {
         let value = settings.mic_volume as f64;
-        element.set_property("volume", &value);
+        element.set_property("volume", value);
     }
 }
 
 fn replace_capture(state: &State<'_, Mutex<CaptureRuntime>>, new_capture: Option<GstCapture>) {
+    // A stopped capture keeps the label; its packets are still buffered.
+    let device_label = new_capture.as_ref().map(|_| {
+        let device_id = state.lock().unwrap().settings.video_device_id.clone();
+        list_video_devices_inner()
+            .into_iter()
+            .find(|device| device.id == device_id)
+            .map(|device| device.label)
+    });
+
     let mut guard = state.lock().unwrap();
     guard.capture = new_capture;
+    if let Some(device_label) = device_label {
+        guard.device_label = device_label;
+    }
+}
+
+// Segment files rotate at this size so evicted history is freed from disk promptly.
+const SPILL_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+fn apply_buffer_settings(rb: &mut RingBuffer, settings: &UserSettings) -> Result<(), String> {
+    rb.set_max_duration_ms(u64::from(settings.buffer_seconds) * 1000);
+    rb.set_max_bytes(buffer_max_bytes(settings));
+
+    let wants_disk = settings.buffer_backend == BufferBackend::Disk;
+    if wants_disk != rb.is_spilling() {
+        let spill = if wants_disk {
+            let dir = spill_dir().map_err(|err| err.to_string())?;
+            let store =
+                SpillStore::open(&dir, SPILL_SEGMENT_BYTES).map_err(|err| err.to_string())?;
+            logger::info(
+                "buffer",
+                format!("spilling replay buffer to {}", dir.display()),
+            );
+            Some(store)
+        } else {
+            None
+        };
+        rb.set_spill_store(spill);
+    }
+
+    Ok(())
 }
 
 fn resolve_settings() -> Result<UserSettings, String> {
@@ -120,7 +259,7 @@ fn resolve_settings() -> Result<UserSettings, String> {
         apply_startup_fallbacks(settings.clone(), &video_devices, &microphones, &encoders);
     if !changes.is_empty() {
         for change in &changes {
-            logger::info("settings", format!("{}", change));
+            logger::info("settings", change.to_string());
         }
         settings = validated;
         save_settings(&settings).map_err(|err| err.to_string())?;
@@ -132,12 +271,21 @@ fn resolve_settings() -> Result<UserSettings, String> {
 }
 
 fn build_runtime() -> Result<CaptureRuntime, String> {
-    let ring_buffer = Arc::new(Mutex::new(RingBuffer::new(30_000)));
     let settings = resolve_settings()?;
+    let mut ring_buffer = RingBuffer::new(u64::from(settings.buffer_seconds) * 1000);
+    if let Err(err) = apply_buffer_settings(&mut ring_buffer, &settings) {
+        logger::error(
+            "buffer",
+            format!("failed to configure replay buffer: {}", err),
+        );
+    }
+    let ring_buffer = Arc::new(Mutex::new(ring_buffer));
     Ok(CaptureRuntime {
         settings,
         capture: None,
         ring_buffer,
+        exports: HashMap::new(),
+        device_label: None,
     })
 }
 
@@ -161,9 +309,19 @@ fn get_status(state: State<'_, Mutex<CaptureRuntime>>) -> StatusResponse {
         buffering: guard.capture.is_some(),
         buffer_seconds,
         ring_buffer_packets: rb.len(),
+        ring_buffer_bytes: rb.bytes(),
+        ring_buffer_max_bytes: rb.max_bytes(),
+        capture_stats: guard.capture.as_ref().map(|capture| capture.stats()),
     }
 }
 
+#[tauri::command]
+fn get_buffer_stats(state: State<'_, Mutex<CaptureRuntime>>) -> RingBufferStats {
+    let guard = state.lock().unwrap();
+    let rb = guard.ring_buffer.lock().unwrap();
+    rb.stats()
+}
+
 #[tauri::command]
 fn list_video_devices() -> Vec<VideoDevice> {
     list_video_devices_inner()
@@ -203,14 +361,12 @@ fn update_settings(
         err.to_string()
     })?;
 
-    if let Err(message) = validate_settings(&new_settings, &video_devices, &microphones, &encoders)
-    {
-        return Err(message);
-    }
+    validate_settings(&new_settings, &video_devices, &microphones, &encoders)?;
 
-    let (old_capture, should_restart, saved_settings, volume_targets, volume_changed) = {
+    let (old_capture, should_restart, keep_buffer, saved_settings, volume_targets, volume_changed) = {
         let mut guard = state.lock().unwrap();
         let restart = should_restart_capture(&guard.settings, &new_settings);
+        let keep_buffer = streams_compatible(&guard.settings, &new_settings);
         let volume_changed = guard.settings.system_audio_volume != new_settings.system_audio_volume
             || guard.settings.mic_volume != new_settings.mic_volume;
         let volume_targets = if !restart {
@@ -227,11 +383,13 @@ fn update_settings(
         } else {
             (None, None)
         };
+        apply_buffer_settings(&mut guard.ring_buffer.lock().unwrap(), &new_settings)?;
         guard.settings = new_settings.clone();
         let captured = if restart { guard.capture.take() } else { None };
         (
             captured,
             restart,
+            keep_buffer,
             guard.settings.clone(),
             volume_targets,
             volume_changed,
@@ -254,7 +412,12 @@ fn update_settings(
         };
         {
             let mut rb = ring_buffer.lock().unwrap();
-            rb.clear();
+            if keep_buffer {
+                rb.continue_timeline();
+            } else {
+                logger::info("capture", "stream layout changed, clearing replay buffer");
+                rb.clear();
+            }
         }
         let new_capture = GstCapture::start(&saved_settings, ring_buffer).map_err(|err| {
             let message = err.to_string();
@@ -289,8 +452,9 @@ fn start_capture(app: AppHandle, state: State<'_, Mutex<CaptureRuntime>>) -> Res
     }
 
     {
+        // Settings are unchanged since the buffer was filled, so history is kept.
         let mut rb = ring_buffer.lock().unwrap();
-        rb.clear();
+        rb.continue_timeline();
     }
 
     let capture = GstCapture::start(&settings, ring_buffer).map_err(|err| {
@@ -335,7 +499,7 @@ fn restart_capture(app: AppHandle, state: State<'_, Mutex<CaptureRuntime>>) -> R
     drop(old_capture);
     {
         let mut rb = ring_buffer.lock().unwrap();
-        rb.clear();
+        rb.continue_timeline();
     }
 
     let capture = GstCapture::start(&settings, ring_buffer).map_err(|err| {
@@ -369,7 +533,7 @@ fn set_audio_volume(
 
     if let Some(element) = target {
         let value = value as f64;
-        element.set_property("volume", &value);
+        element.set_property("volume", value);
         Ok(())
     } else {
         Err("volume control not available".to_string())
@@ -377,42 +541,593 @@ fn set_audio_volume(
 }
 
 #[tauri::command]
-async fn clip(state: State<'_, Mutex<CaptureRuntime>>) -> Result<ClipResponse, String> {
-    let (packets, clips_dir) = {
+async fn clip(
+    app: AppHandle,
+    state: State<'_, Mutex<CaptureRuntime>>,
+    duration_secs: Option<u32>,
+    format: Option<ClipFormat>,
+    title: Option<String>,
+) -> Result<ClipResponse, String> {
+    let (pre_roll, post_roll, markers, clips_dir, clip_format, settings, device_label) = {
         let guard = state.lock().unwrap();
-        let mut rb = guard.ring_buffer.lock().unwrap();
-        let packets = rb.drain_from_keyframe();
-        (packets, guard.settings.clips_dir.clone())
-    };
+        let pre_roll_ms = duration_secs.map(|secs| u64::from(secs) * 1000);
+        let post_roll_secs = guard.settings.post_roll_secs;
 
-    if packets.is_empty() {
-        return Err("no packets available".to_string());
-    }
+        let (pre_roll, post_roll) = match guard.capture.as_ref() {
+            Some(capture) if post_roll_secs > 0 => {
+                let post_roll_ms = u64::from(post_roll_secs) * 1000;
+                let (pre_roll, receiver) = capture.tap_packets(pre_roll_ms, post_roll_ms);
+                (pre_roll, Some((receiver, post_roll_ms)))
+            }
+            _ => {
+                let rb = guard.ring_buffer.lock().unwrap();
+                let packets = match pre_roll_ms {
+                    Some(ms) => rb.stream_last(ms),
+                    None => rb.stream_from_keyframe(),
+                };
+                (packets, None)
+            }
+        };
 
-    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-    let filename = format!("clip-{}.mp4", timestamp);
+        if pre_roll.is_empty() {
+            return Err("no packets available".to_string());
+        }
 
-    let mut path = PathBuf::from(clips_dir);
-    fs::create_dir_all(&path).map_err(|e| e.to_string())?;
-    path.push(&filename);
+        // Markers are taken at the press, up to the end of the post-roll.
+        let markers = pre_roll
+            .dts_range()
+            .map(|(first, last)| {
+                let post_roll_ns = post_roll.as_ref().map_or(0, |(_, ms)| ms * 1_000_000);
+                guard
+                    .ring_buffer
+                    .lock()
+                    .unwrap()
+                    .markers_between(first, last.saturating_add(post_roll_ns))
+            })
+            .unwrap_or_default();
+        (
+            pre_roll,
+            post_roll,
+            markers,
+            guard.settings.clips_dir.clone(),
+            format.unwrap_or(guard.settings.clip_format),
+            guard.settings.clone(),
+            guard.device_label.clone(),
+        )
+    };
+
+    let now = Local::now();
+    let metadata = ClipMetadata {
+        created_at: now.to_rfc3339(),
+        device_label,
+        encoder_id: settings.video_encoder_id.clone(),
+        bitrate_kbps: settings.bitrate_kbps,
+        title: title.filter(|title| !title.trim().is_empty()),
+    };
+
+    let clips_dir = PathBuf::from(clips_dir);
+    fs::create_dir_all(&clips_dir).map_err(|e| e.to_string())?;
+    // Back-to-back saves within a second get -2, -3, ... instead of
+    // overwriting each other.
+    let (filename, path) = reserve_output(
+        &clips_dir,
+        &format!("clip-{}", now.format("%Y-%m-%d_%H-%M-%S")),
+        clip_format.extension(),
+    )?;
 
-    let packets_clone = packets.clone();
     let path_clone = path.clone();
 
+    let cancel = CancelToken::new();
+    state
+        .lock()
+        .unwrap()
+        .exports
+        .insert(filename.clone(), cancel.clone());
+
+    let app_clone = app.clone();
+    let filename_clone = filename.clone();
+
+    // The pre-roll and post-roll are pulled only as fast as the muxer takes
+    // them, so neither has to sit in memory as a whole.
     let result = tauri::async_runtime::spawn_blocking(move || {
-        clip_service::remux::remux_ts_to_mp4(&packets_clone, &path_clone)
+        let job = RemuxJob {
+            markers: &markers,
+            metadata: Some(&metadata),
+            format: clip_format,
+            cancel: Some(cancel.clone()),
+        };
+        let mut packet_count = 0;
+        let on_remux_progress =
+            |progress| emit_clip_progress(&app_clone, &filename_clone, "remuxing", progress);
+
+        emit_clip_progress(&app_clone, &filename_clone, "remuxing", 0.0);
+
+        let result = match post_roll {
+            Some((receiver, post_roll_ms)) => {
+                let packets =
+                    PostRoll::new(pre_roll, receiver, post_roll_ms, Some(cancel), |progress| {
+                        emit_clip_progress(&app_clone, &filename_clone, "post_roll", progress)
+                    });
+                job.run_stream(
+                    packets.inspect(|_| packet_count += 1),
+                    &path_clone,
+                    on_remux_progress,
+                )
+            }
+            None => job.run_stream(
+                pre_roll.inspect(|_| packet_count += 1),
+                &path_clone,
+                on_remux_progress,
+            ),
+        };
+
+        result.map(|result| (result, packet_count))
     })
     .await
-    .map_err(|e| e.to_string())??;
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    state.lock().unwrap().exports.remove(&filename);
+
+    let result = result.inspect_err(|_| {
+        let _ = fs::remove_file(&path);
+    });
+
+    let (result, packet_count) = match result {
+        Ok(result) => result,
+        Err(err) if err == CANCELLED => {
+            emit_clip_progress(&app, &filename, "cancelled", 0.0);
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+
+    emit_clip_progress(&app, &filename, "verifying", 0.0);
+
+    let expect_audio = has_audio(&settings);
+    let verify_path = path.clone();
+    let verification = tauri::async_runtime::spawn_blocking(move || {
+        clip_service::remux::verify_clip(&verify_path, &result, clip_format, expect_audio)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // The clip is saved either way; the report tells the UI whether to trust it.
+    let verification = match verification {
+        Ok(report) if !report.is_ok() => {
+            logger::warn(
+                "capture",
+                format!(
+                    "clip {} failed verification: {}",
+                    filename,
+                    report.problems.join("; ")
+                ),
+            );
+            Some(report)
+        }
+        Ok(report) => Some(report),
+        Err(err) => {
+            logger::warn(
+                "capture",
+                format!("clip verification failed to run: {}", err),
+            );
+            None
+        }
+    };
 
     logger::info("capture", format!("Clip saved to {}", path.display()));
+    emit_clip_progress(&app, &filename, "saved", 1.0);
+
+    // Posters are a nicety for the clip list; don't hold the save up for them.
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(err) =
+            clip_service::remux::generate_thumbnails(&path, &ThumbnailOptions::default())
+        {
+            logger::warn("capture", format!("thumbnail failed: {}", err));
+        }
+    });
 
     Ok(ClipResponse {
         filename,
-        packets: packets.len(),
+        packets: packet_count,
         duration_ms: result.duration_ms,
         bytes: result.bytes_written as usize,
+        verification,
+    })
+}
+
+#[tauri::command]
+fn add_marker(
+    state: State<'_, Mutex<CaptureRuntime>>,
+    label: Option<String>,
+) -> Result<(), String> {
+    let guard = state.lock().unwrap();
+    let mut rb = guard.ring_buffer.lock().unwrap();
+    let label = label
+        .filter(|label| !label.trim().is_empty())
+        .unwrap_or_else(|| "marker".to_string());
+
+    let marker = rb
+        .add_marker(label)
+        .ok_or_else(|| "replay buffer is empty".to_string())?;
+
+    logger::info("capture", format!("marker added: {}", marker.label));
+    Ok(())
+}
+
+// Reserves `{base}.{extension}` in the clips directory, or `{base}-2.{extension}`
+// and so on when that exists, by creating it empty. The job then overwrites it.
+fn reserve_output(
+    clips_dir: &Path,
+    base: &str,
+    extension: &str,
+) -> Result<(String, PathBuf), String> {
+    for attempt in 1u32.. {
+        let name = match attempt {
+            1 => format!("{}.{}", base, extension),
+            n => format!("{}-{}.{}", base, n, extension),
+        };
+        let path = clips_dir.join(&name);
+
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => return Ok((name, path)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+
+    Err(format!("no free file name for {}", base))
+}
+
+// Resolves a clip filename from the UI inside the clips directory.
+fn clip_path(clips_dir: &Path, filename: &str) -> Result<PathBuf, String> {
+    let name = Path::new(filename);
+    if name.file_name() != Some(name.as_os_str()) {
+        return Err(format!("invalid clip name: {}", filename));
+    }
+
+    let path = clips_dir.join(name);
+    if !path.is_file() {
+        return Err(format!("clip not found: {}", filename));
+    }
+
+    Ok(path)
+}
+
+#[tauri::command]
+async fn trim_clip(
+    state: State<'_, Mutex<CaptureRuntime>>,
+    filename: String,
+    start_ms: u64,
+    end_ms: u64,
+    mode: Option<TrimMode>,
+) -> Result<ExportResponse, String> {
+    let (clips_dir, encoder) = {
+        let guard = state.lock().unwrap();
+        (
+            PathBuf::from(guard.settings.clips_dir.clone()),
+            VideoEncoder::from_settings(&guard.settings).map_err(|e| e.to_string())?,
+        )
+    };
+
+    let input = clip_path(&clips_dir, &filename)?;
+    let stem = input
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("clip");
+    let extension = input
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mp4");
+    // Never overwrites an earlier trim of the same range.
+    let (output_name, output) = reserve_output(
+        &clips_dir,
+        &format!("{}-trim-{}-{}", stem, start_ms / 1000, end_ms / 1000),
+        extension,
+    )?;
+    let output_clone = output.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        clip_service::remux::trim_clip(
+            &input,
+            &output_clone,
+            start_ms,
+            end_ms,
+            mode.unwrap_or_default(),
+            &encoder,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    let result = result.inspect_err(|_| {
+        let _ = fs::remove_file(&output);
+    })?;
+
+    logger::info("capture", format!("Trimmed clip saved to {}", output_name));
+
+    Ok(ExportResponse {
+        filename: output_name,
+        duration_ms: result.duration_ms,
+        bytes: result.bytes_written,
+    })
+}
+
+#[tauri::command]
+async fn concat_clips(
+    state: State<'_, Mutex<CaptureRuntime>>,
+    filenames: Vec<String>,
+    format: Option<ClipFormat>,
+) -> Result<ExportResponse, String> {
+    let (clips_dir, encoder, default_format) = {
+        let guard = state.lock().unwrap();
+        (
+            PathBuf::from(guard.settings.clips_dir.clone()),
+            VideoEncoder::from_settings(&guard.settings).map_err(|e| e.to_string())?,
+            guard.settings.clip_format,
+        )
+    };
+
+    let inputs = filenames
+        .iter()
+        .map(|filename| clip_path(&clips_dir, filename))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Compilations are MP4 or MKV; TS clips are joined into an MP4.
+    let extension = match format.unwrap_or(default_format) {
+        ClipFormat::Mkv => "mkv",
+        _ => "mp4",
+    };
+    let (output_name, output) = reserve_output(
+        &clips_dir,
+        &format!("compilation-{}", Local::now().format("%Y-%m-%d_%H-%M-%S")),
+        extension,
+    )?;
+    let output_clone = output.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        clip_service::remux::concat_clips(&inputs, &output_clone, &encoder)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    let result = result.inspect_err(|_| {
+        let _ = fs::remove_file(&output);
+    })?;
+
+    logger::info("capture", format!("Compilation saved to {}", output_name));
+
+    Ok(ExportResponse {
+        filename: output_name,
+        duration_ms: result.duration_ms,
+        bytes: result.bytes_written,
+    })
+}
+
+#[tauri::command]
+async fn export_to_size(
+    app: AppHandle,
+    state: State<'_, Mutex<CaptureRuntime>>,
+    filename: String,
+    target_mb: u32,
+    max_height: Option<u32>,
+    encoder_id: Option<String>,
+) -> Result<ExportResponse, String> {
+    if target_mb == 0 {
+        return Err("target size must be at least 1 MB".to_string());
+    }
+
+    let (clips_dir, export) = {
+        let guard = state.lock().unwrap();
+        (
+            PathBuf::from(guard.settings.clips_dir.clone()),
+            SizeExport {
+                target_bytes: u64::from(target_mb) * 1_000_000,
+                max_height,
+                encoder_id: encoder_id.unwrap_or_else(|| guard.settings.video_encoder_id.clone()),
+            },
+        )
+    };
+
+    let input = clip_path(&clips_dir, &filename)?;
+    let stem = input
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("clip");
+    let (output_name, output) =
+        reserve_output(&clips_dir, &format!("{}-{}mb", stem, target_mb), "mp4")?;
+    let output_clone = output.clone();
+
+    let cancel = CancelToken::new();
+    state
+        .lock()
+        .unwrap()
+        .exports
+        .insert(output_name.clone(), cancel.clone());
+
+    let app_clone = app.clone();
+    let name_clone = output_name.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        clip_service::remux::export_to_size(&input, &output_clone, &export, &cancel, |progress| {
+            emit_clip_progress(&app_clone, &name_clone, "exporting", progress)
+        })
     })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    state.lock().unwrap().exports.remove(&output_name);
+
+    let result = result.inspect_err(|_| {
+        let _ = fs::remove_file(&output);
+    });
+
+    match result {
+        Ok(result) => {
+            logger::info("capture", format!("Export saved to {}", output_name));
+            emit_clip_progress(&app, &output_name, "saved", 1.0);
+
+            Ok(ExportResponse {
+                filename: output_name,
+                duration_ms: result.duration_ms,
+                bytes: result.bytes_written,
+            })
+        }
+        Err(err) if err == CANCELLED => {
+            emit_clip_progress(&app, &output_name, "cancelled", 0.0);
+            Err(err)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[tauri::command]
+async fn export_animation(
+    app: AppHandle,
+    state: State<'_, Mutex<CaptureRuntime>>,
+    range: AnimationRange,
+    export: AnimationExport,
+) -> Result<ExportResponse, String> {
+    range.validate()?;
+
+    let (clips_dir, packets) = {
+        let guard = state.lock().unwrap();
+        let packets = match range {
+            AnimationRange::Clip { .. } => None,
+            AnimationRange::Buffer {
+                start_ago_ms,
+                end_ago_ms,
+            } => {
+                // Only the window's bounds are taken under the locks; spilled
+                // payloads are read as the export pushes them.
+                let rb = guard.ring_buffer.lock().unwrap();
+                Some(rb.stream_window(start_ago_ms, end_ago_ms))
+            }
+        };
+        (PathBuf::from(guard.settings.clips_dir.clone()), packets)
+    };
+
+    let (input, base) = match &range {
+        AnimationRange::Clip {
+            filename,
+            start_ms,
+            end_ms,
+        } => {
+            let input = clip_path(&clips_dir, filename)?;
+            let stem = input
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("clip");
+            let base = format!("{}-{}-{}", stem, start_ms / 1000, end_ms / 1000);
+            (Some((input, *start_ms, *end_ms)), base)
+        }
+        AnimationRange::Buffer { .. } => (
+            None,
+            format!("buffer-{}", Local::now().format("%Y-%m-%d_%H-%M-%S")),
+        ),
+    };
+
+    fs::create_dir_all(&clips_dir).map_err(|e| e.to_string())?;
+    let (output_name, output) = reserve_output(&clips_dir, &base, export.format.extension())?;
+    let output_clone = output.clone();
+
+    let cancel = CancelToken::new();
+    state
+        .lock()
+        .unwrap()
+        .exports
+        .insert(output_name.clone(), cancel.clone());
+
+    let app_clone = app.clone();
+    let name_clone = output_name.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let source = match (input.as_ref(), packets) {
+            (Some((path, start_ms, end_ms)), _) => AnimationSource::Clip {
+                path,
+                start_ms: *start_ms,
+                end_ms: *end_ms,
+            },
+            (None, Some(packets)) => AnimationSource::Packets(packets),
+            (None, None) => return Err("nothing to export".to_string()),
+        };
+
+        clip_service::remux::export_animation(source, &output_clone, &export, &cancel, |progress| {
+            emit_clip_progress(&app_clone, &name_clone, "exporting", progress)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    state.lock().unwrap().exports.remove(&output_name);
+
+    let result = result.inspect_err(|_| {
+        let _ = fs::remove_file(&output);
+    });
+
+    match result {
+        Ok(result) => {
+            logger::info("capture", format!("Animation saved to {}", output_name));
+            emit_clip_progress(&app, &output_name, "saved", 1.0);
+
+            Ok(ExportResponse {
+                filename: output_name,
+                duration_ms: result.duration_ms,
+                bytes: result.bytes_written,
+            })
+        }
+        Err(err) if err == CANCELLED => {
+            emit_clip_progress(&app, &output_name, "cancelled", 0.0);
+            Err(err)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[tauri::command]
+async fn generate_thumbnails(
+    state: State<'_, Mutex<CaptureRuntime>>,
+    filename: String,
+    options: Option<ThumbnailOptions>,
+) -> Result<ThumbnailResponse, String> {
+    let clips_dir = {
+        let guard = state.lock().unwrap();
+        PathBuf::from(guard.settings.clips_dir.clone())
+    };
+    let input = clip_path(&clips_dir, &filename)?;
+
+    let thumbnails = tauri::async_runtime::spawn_blocking(move || {
+        clip_service::remux::generate_thumbnails(&input, &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    Ok(ThumbnailResponse {
+        poster: thumbnails.poster.to_string_lossy().to_string(),
+        contact_sheet: thumbnails
+            .contact_sheet
+            .map(|path| path.to_string_lossy().to_string()),
+    })
+}
+
+#[tauri::command]
+fn cancel_export(state: State<'_, Mutex<CaptureRuntime>>, filename: String) -> Result<(), String> {
+    let guard = state.lock().unwrap();
+    let cancel = guard
+        .exports
+        .get(&filename)
+        .ok_or_else(|| format!("no running export for {}", filename))?;
+
+    cancel.cancel();
+    Ok(())
 }
 
 #[tauri::command]
@@ -426,11 +1141,16 @@ fn list_clips(state: State<'_, Mutex<CaptureRuntime>>) -> Vec<ClipInfo> {
     if let Ok(entries) = fs::read_dir(&clips_dir) {
         for entry in entries.flatten() {
             if let Ok(metadata) = entry.metadata() {
-                if metadata.is_file() {
+                let path = entry.path();
+                let is_sidecar = path.extension().is_some_and(|ext| ext == "json");
+                if metadata.is_file() && !is_sidecar && !is_thumbnail(&path) {
                     if let Some(name) = entry.file_name().to_str() {
                         clips.push(ClipInfo {
                             filename: name.to_string(),
                             size_bytes: metadata.len(),
+                            thumbnail: find_thumbnail(&path)
+                                .map(|thumb| thumb.to_string_lossy().to_string()),
+                            metadata: read_sidecar(&path).and_then(|sidecar| sidecar.metadata),
                         });
                     }
                 }
@@ -479,12 +1199,12 @@ pub fn run() {
                 match GstCapture::start(&settings, ring_buffer) {
                     Ok(capture) => {
                         replace_capture(&state, Some(capture));
-                        emit_capture_status(&app.handle(), "running", None);
+                        emit_capture_status(app.handle(), "running", None);
                     }
                     Err(err) => {
                         let message = err.to_string();
                         logger::error("capture", format!("startup failed: {}", message));
-                        emit_capture_status(&app.handle(), "error", Some(message));
+                        emit_capture_status(app.handle(), "error", Some(message));
                     }
                 }
             }
@@ -495,6 +1215,7 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             get_status,
+            get_buffer_stats,
             list_video_devices,
             list_microphone_devices,
             list_video_encoders,
@@ -506,6 +1227,13 @@ pub fn run() {
             restart_capture,
             set_audio_volume,
             clip,
+            add_marker,
+            trim_clip,
+            concat_clips,
+            export_to_size,
+            export_animation,
+            generate_thumbnails,
+            cancel_export,
             list_clips,
             get_clips_dir
         ])
@@ -8,4 +8,10 @@ export type UserSettings = {
     framerate: number;
     bitrate_kbps: number;
     clips_dir: string;
+    buffer_seconds: number;
+    buffer_max_mb?: number | null;
+    buffer_disk_max_mb?: number | null;
+    buffer_backend: "memory" | "disk";
+    post_roll_secs: number;
+    clip_format: "mp4" | "mkv" | "fragmented_mp4" | "ts";
 };